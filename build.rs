@@ -0,0 +1,22 @@
+// With the `capi` feature, generates the C header from src/cpu/capi.rs into
+// OUT_DIR. A test checks that include/sfoxidized.h is a copy of it.
+
+#[cfg(feature = "capi")]
+extern crate cbindgen;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "capi")]
+    {
+        let out = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/cpu/capi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file("cbindgen.toml").expect("cannot read cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/cpu/capi.rs")
+            .generate()
+            .expect("cannot generate the C header")
+            .write_to_file(std::path::Path::new(&out).join("sfoxidized.h"));
+    }
+}
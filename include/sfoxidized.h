@@ -0,0 +1,129 @@
+/*
+ * C API of the sfoxidized CPU core, generated from src/cpu/capi.rs by
+ * cbindgen; do not edit. Build the library with
+ *
+ *     cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * and link against target/release/libsfoxidized.so (or .dylib, .dll).
+ *
+ * Every pointer argument must be valid and not NULL, except for the one
+ * sfox_cpu_free takes; passing NULL anywhere else is undefined behaviour.
+ * The user pointer in sfox_bus is only handed back to the callbacks and
+ * may be anything.
+ */
+
+#ifndef SFOXIDIZED_H
+#define SFOXIDIZED_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/**
+ * Status codes returned by `sfox_cpu_step` and `sfox_cpu_run`.
+ */
+#define SFOX_OK 0
+
+#define SFOX_ILLEGAL_OPCODE 1
+
+#define SFOX_UNIMPLEMENTED 2
+
+#define SFOX_UNMAPPED 3
+
+#define SFOX_HALTED 4
+
+/**
+ * A CPU as handed out to C, `sfox_cpu`, which only sees a pointer to it.
+ */
+typedef struct sfox_cpu sfox_cpu;
+
+/**
+ * The bus, `sfox_bus` in C. `read` and `write` are required. `peek` reads
+ * without side effects and is only used to describe errors; if it is
+ * NULL, the instructions listed in `sfox_cpu_error` show the open bus.
+ */
+typedef struct sfox_bus {
+  void *user;
+  uint8_t (*read)(void *user, uint16_t address);
+  void (*write)(void *user, uint16_t address, uint8_t value);
+  uint8_t (*peek)(void *user, uint16_t address);
+} sfox_bus;
+
+/**
+ * The registers, `sfox_registers` in C. P reads with bit 5 set and B
+ * clear, like a trace shows it.
+ */
+typedef struct sfox_registers {
+  uint16_t pc;
+  uint8_t a;
+  uint8_t x;
+  uint8_t y;
+  uint8_t s;
+  uint8_t p;
+} sfox_registers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a CPU on the given bus, with all registers zero; call
+ * `sfox_cpu_reset` to start from the reset vector. Returns NULL if `read`
+ * or `write` is missing. The callbacks are copied.
+ */
+struct sfox_cpu *sfox_cpu_new(const struct sfox_bus *callbacks);
+
+/**
+ * Destroys a CPU. Passing NULL does nothing.
+ */
+void sfox_cpu_free(struct sfox_cpu *cpu);
+
+/**
+ * Runs the reset sequence, which loads PC from the reset vector.
+ */
+void sfox_cpu_reset(struct sfox_cpu *cpu);
+
+/**
+ * Executes one instruction, or services a pending interrupt.
+ */
+int sfox_cpu_step(struct sfox_cpu *cpu);
+
+/**
+ * Executes instructions until at least `cycles` cycles have passed. The
+ * last instruction may overshoot the budget; `sfox_cpu_cycles` tells by
+ * how much. Stops early with `SFOX_HALTED` when the CPU locks up.
+ */
+int sfox_cpu_run(struct sfox_cpu *cpu, uint64_t cycles);
+
+/**
+ * The number of cycles executed since the CPU was created.
+ */
+uint64_t sfox_cpu_cycles(const struct sfox_cpu *cpu);
+
+/**
+ * Drives the IRQ line. The interrupt is taken before the next
+ * instruction for as long as the line is active and I is clear.
+ */
+void sfox_cpu_set_irq(struct sfox_cpu *cpu, bool active);
+
+/**
+ * Drives the NMI line. NMI is edge triggered: only a change from
+ * inactive to active causes an interrupt.
+ */
+void sfox_cpu_set_nmi(struct sfox_cpu *cpu, bool active);
+
+void sfox_cpu_get_registers(const struct sfox_cpu *cpu, struct sfox_registers *registers);
+
+void sfox_cpu_set_registers(struct sfox_cpu *cpu, const struct sfox_registers *registers);
+
+/**
+ * Describes why the last `sfox_cpu_step` or `sfox_cpu_run` failed, or
+ * returns NULL if it succeeded. The string belongs to the CPU and stays
+ * valid until the next step, run or free.
+ */
+const char *sfox_cpu_error(const struct sfox_cpu *cpu);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SFOXIDIZED_H */
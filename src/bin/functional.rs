@@ -0,0 +1,166 @@
+// Runs the classic 6502 functional test images (Klaus Dormann's functional,
+// decimal and interrupt tests, and others built the same way): a flat image
+// is loaded into memory, the CPU starts at the entry point and runs until it
+// traps in an instruction that jumps or branches to itself. Reaching the
+// success trap means every test passed; any other trap identifies the
+// failing test.
+//
+//     cargo run --release --bin functional -- 6502_functional_test.bin
+//     cargo run --release --bin functional -- 6502_interrupt_test.bin --success 06f5 --feedback bffc
+//     cargo run --release --bin functional -- 6502_decimal_test.bin --entry 0200 --result 000b
+
+extern crate sfoxidized;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+use sfoxidized::cpu::CpuBuilder;
+use sfoxidized::cpu::disassembler::disassemble;
+use sfoxidized::cpu::symbols::SymbolTable;
+
+const DEFAULT_ENTRY: u16 = 0x0400;
+// the success trap of the prebuilt 6502_functional_test.bin
+const DEFAULT_SUCCESS: u16 = 0x3469;
+// where the functional tests keep the number of the test in progress
+const DEFAULT_TEST_CASE: u16 = 0x0200;
+const DEFAULT_MAX_CYCLES: u64 = 1_000_000_000;
+
+struct Options {
+    image: String,
+    load: u16,
+    entry: u16,
+    success: Option<u16>,
+    result: Option<u16>,
+    test_case: u16,
+    feedback: Option<u16>,
+    max_cycles: u64,
+    symbols: Option<String>,
+    quiet: bool
+}
+
+fn usage() -> ! {
+    eprintln!("usage: functional [options] <image.bin>");
+    eprintln!("  --load <addr>        load the image at addr (default 0000)");
+    eprintln!("  --entry <addr>       start executing at addr (default {:04x})", DEFAULT_ENTRY);
+    eprintln!("  --success <addr>     address of the success trap (default {:04x})", DEFAULT_SUCCESS);
+    eprintln!("  --result <addr>      judge by the byte at addr instead, 0 meaning success");
+    eprintln!("  --test-case <addr>   address holding the current test number (default {:04x})", DEFAULT_TEST_CASE);
+    eprintln!("  --feedback <addr>    interrupt feedback port: bit 0 drives IRQ, bit 1 drives NMI");
+    eprintln!("  --max-cycles <n>     give up after n cycles (default {})", DEFAULT_MAX_CYCLES);
+    eprintln!("  --symbols <file>     label file used to name the trap address");
+    eprintln!("  -q                   only report the outcome");
+    eprintln!("Exits with 0 on success, 1 on a failing test, 2 on usage errors and 3 on a timeout.");
+    process::exit(2);
+}
+
+fn parse_address(text: &str) -> u16 {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).unwrap_or_else(|_| {
+        eprintln!("functional: not an address: {}", text);
+        usage();
+    })
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        image: String::new(),
+        load: 0,
+        entry: DEFAULT_ENTRY,
+        success: None,
+        result: None,
+        test_case: DEFAULT_TEST_CASE,
+        feedback: None,
+        max_cycles: DEFAULT_MAX_CYCLES,
+        symbols: None,
+        quiet: false
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--load" => options.load = parse_address(&value()),
+            "--entry" => options.entry = parse_address(&value()),
+            "--success" => options.success = Some(parse_address(&value())),
+            "--result" => options.result = Some(parse_address(&value())),
+            "--test-case" => options.test_case = parse_address(&value()),
+            "--feedback" => options.feedback = Some(parse_address(&value())),
+            "--max-cycles" => options.max_cycles = value().parse().unwrap_or_else(|_| usage()),
+            "--symbols" => options.symbols = Some(value()),
+            "-q" => options.quiet = true,
+            "-h" | "--help" => usage(),
+            _ if options.image.is_empty() && !arg.starts_with('-') => options.image = arg,
+            _ => usage()
+        }
+    }
+    if options.image.is_empty() {
+        usage();
+    }
+    options
+}
+
+fn describe(address: u16, symbols: &Option<SymbolTable>) -> String {
+    match symbols.as_ref().and_then(|s| s.describe(address)) {
+        Some(name) => format!("${:04X} ({})", address, name),
+        None => format!("${:04X}", address)
+    }
+}
+
+fn main() {
+    let options = parse_options();
+    let image = fs::read(&options.image).unwrap_or_else(|e| {
+        eprintln!("functional: {}: {}", options.image, e);
+        process::exit(2);
+    });
+    if options.load as usize + image.len() > 0x10000 {
+        eprintln!("functional: {} does not fit in memory at ${:04X}", options.image, options.load);
+        process::exit(2);
+    }
+    let symbols = options.symbols.as_ref().map(|path| SymbolTable::load(path).unwrap_or_else(|e| {
+        eprintln!("functional: {}", e);
+        process::exit(2);
+    }));
+
+    let mut cpu = CpuBuilder::new().load(options.load, &image).pc(options.entry).build();
+
+    let start = Instant::now();
+    let mut instructions = 0u64;
+    let trap = loop {
+        let pc = cpu.pc();
+        if let Err(e) = cpu.step() {
+            eprintln!("functional: stopped at {}: {}", describe(pc, &symbols), e);
+            process::exit(1);
+        }
+        instructions += 1;
+        if let Some(port) = options.feedback {
+            let lines = cpu.peek(port);
+            cpu.set_irq(lines & 0x01 != 0);
+            cpu.set_nmi(lines & 0x02 != 0);
+        }
+        if cpu.pc() == pc {
+            break pc;
+        }
+        if cpu.cycles() >= options.max_cycles {
+            eprintln!("functional: no trap after {} cycles, last at {}", cpu.cycles(), describe(pc, &symbols));
+            process::exit(3);
+        }
+    };
+
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    if !options.quiet {
+        println!("trapped at {} after {} instructions, {} cycles ({:.2} MHz emulated)",
+                 describe(trap, &symbols), instructions, cpu.cycles(), cpu.cycles() as f64 / secs / 1e6);
+    }
+    let passed = match options.result {
+        Some(address) => cpu.peek(address) == 0,
+        None => trap == options.success.unwrap_or(DEFAULT_SUCCESS)
+    };
+    if passed {
+        println!("success");
+        return;
+    }
+    let line = disassemble(|address| cpu.peek(address), trap);
+    println!("failed in test ${:02X}, trap: {}", cpu.peek(options.test_case), line.text);
+    process::exit(1);
+}
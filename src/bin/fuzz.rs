@@ -0,0 +1,51 @@
+// Differentially fuzzes the CPU core against the reference interpreter in
+// `cpu::fuzz`, one random instruction at a time, and prints the first case
+// on which they disagree.
+//
+//     cargo run --release --bin fuzz -- [--seed <n>] [--cases <n>]
+//
+// Without a seed one is taken from the clock; it is printed so that a
+// failure can be reproduced. Exits with 1 on a divergence.
+
+extern crate sfoxidized;
+
+use std::env;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use sfoxidized::cpu::fuzz;
+
+const DEFAULT_CASES: u64 = 1_000_000;
+
+fn usage() -> ! {
+    eprintln!("usage: fuzz [--seed <n>] [--cases <n>]");
+    process::exit(2);
+}
+
+fn main() {
+    let mut seed = None;
+    let mut cases = DEFAULT_CASES;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|n| n.parse::<u64>().ok()).unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--seed" => seed = Some(number()),
+            "--cases" => cases = number(),
+            _ => usage()
+        }
+    }
+    let seed = seed.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.as_secs() ^ (now.subsec_nanos() as u64) << 32
+    });
+
+    println!("seed {}", seed);
+    let start = Instant::now();
+    match fuzz::run(seed, cases) {
+        Ok(()) => println!("{} cases agree ({:.1}s)", cases, start.elapsed().as_secs() as f64
+                            + start.elapsed().subsec_nanos() as f64 * 1e-9),
+        Err(divergence) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
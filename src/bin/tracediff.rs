@@ -0,0 +1,119 @@
+// Compares two instruction traces and reports the first line where PC,
+// registers, flags or cycle counts diverge.
+//
+//     tracediff [options] ours.log reference.log
+//
+// See `sfoxidized::cpu::tracediff` for how lines are matched up and which
+// fields are compared.
+//
+// Options:
+//     -C <n>              lines of context to print (default 3)
+//     --strict-p          also compare the B and unused bits of P
+//     --absolute-cycles   compare raw cycle counts instead of counts relative
+//                         to each log's first line
+//     --ignore-cycles     don't compare cycle counts at all
+
+extern crate sfoxidized;
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use sfoxidized::cpu::tracediff::{self, Entry, Outcome};
+
+struct Arguments {
+    context: usize,
+    options: tracediff::Options,
+    ours: String,
+    reference: String
+}
+
+fn usage() -> ! {
+    eprintln!("usage: tracediff [-C n] [--strict-p] [--absolute-cycles] [--ignore-cycles] ours.log reference.log");
+    process::exit(2);
+}
+
+fn parse_arguments() -> Arguments {
+    let mut arguments = Arguments {
+        context: 3,
+        options: tracediff::Options::default(),
+        ours: String::new(),
+        reference: String::new()
+    };
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-C" => {
+                arguments.context = args.next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage());
+            },
+            "--strict-p" => arguments.options.strict_p = true,
+            "--absolute-cycles" => arguments.options.absolute_cycles = true,
+            "--ignore-cycles" => arguments.options.ignore_cycles = true,
+            _ if arg.starts_with('-') => usage(),
+            _ => files.push(arg)
+        }
+    }
+    if files.len() != 2 {
+        usage();
+    }
+    arguments.reference = files.pop().unwrap();
+    arguments.ours = files.pop().unwrap();
+    arguments
+}
+
+fn load(path: &str) -> Vec<Entry> {
+    let file = File::open(path).unwrap_or_else(|e| {
+        eprintln!("tracediff: cannot open {}: {}", path, e);
+        process::exit(2);
+    });
+    tracediff::parse_log(BufReader::new(file)).unwrap_or_else(|e| {
+        eprintln!("tracediff: cannot read {}: {}", path, e);
+        process::exit(2);
+    })
+}
+
+fn print_context(label: &str, entries: &[Entry], index: usize, context: usize) {
+    println!("{}:", label);
+    for entry in &entries[index.saturating_sub(context)..index] {
+        println!("  {:>6}  {}", entry.number, entry.text);
+    }
+    if let Some(entry) = entries.get(index) {
+        println!("> {:>6}  {}", entry.number, entry.text);
+    }
+    else {
+        println!(">         <end of log>");
+    }
+}
+
+fn main() {
+    let arguments = parse_arguments();
+    let ours = load(&arguments.ours);
+    let reference = load(&arguments.reference);
+    let context = arguments.context;
+    match tracediff::compare(&arguments.options, &ours, &reference) {
+        Outcome::Agree(count) => {
+            println!("Logs agree for all {} instructions.", count);
+            return;
+        },
+        Outcome::Diverge { index, differences } => {
+            println!("First divergence at instruction {} (ours line {}, reference line {}):",
+                     index + 1, ours[index].number, reference[index].number);
+            for difference in differences {
+                println!("  {}", difference);
+            }
+            println!();
+            print_context(&arguments.ours, &ours, index, context);
+            print_context(&arguments.reference, &reference, index, context);
+        },
+        Outcome::Ends { index, ours_shorter } => {
+            println!("Logs agree for {} instructions, then {} ends.", index,
+                     if ours_shorter { &arguments.ours } else { &arguments.reference });
+            print_context(&arguments.ours, &ours, index, context);
+            print_context(&arguments.reference, &reference, index, context);
+        }
+    }
+    process::exit(1);
+}
@@ -0,0 +1,713 @@
+//! A small two-pass 6502 assembler, meant for writing test programs and
+//! memory patches in readable assembly instead of hand-assembled bytes.
+//!
+//! Supported syntax:
+//!
+//! * `label:` defines a label at the current location, `name = expr` a constant.
+//! * `.org expr` moves the location counter and starts a new segment.
+//! * `.byte expr, "text", ...` and `.word expr, ...` emit data (words little endian).
+//! * The usual operand forms: `#imm`, `zp`, `zp,X`, `abs,Y`, `(zp,X)`, `(zp),Y`,
+//!   `(abs)` and `A` for accumulator mode.
+//! * Expressions with `$hex`, `%binary`, decimal and `'c'` literals, symbols,
+//!   `*` for the current location, `+ - * / % & | ^ << >>`, unary `- ~` and
+//!   `<`/`>` for the low and high byte.
+//!
+//! Comments start with `;`. Mnemonics and register names are case-insensitive,
+//! symbols are not.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use super::{Bus, Cpu};
+use super::instruction::{decode, OperandMode};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Syntax(usize, String),
+    UnknownMnemonic(usize, String),
+    InvalidOperand(usize, String),
+    UndefinedSymbol(usize, String),
+    DuplicateSymbol(usize, String),
+    ValueOutOfRange(usize, i64),
+    BranchOutOfRange(usize, i64),
+    /// An expression that overflows or divides by zero.
+    Arithmetic(usize, String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax(line, ref text) => write!(f, "line {}: syntax error in `{}`", line, text),
+            Error::UnknownMnemonic(line, ref mnemonic) => write!(f, "line {}: unknown mnemonic {}", line, mnemonic),
+            Error::InvalidOperand(line, ref operand) => write!(f, "line {}: invalid operand `{}`", line, operand),
+            Error::UndefinedSymbol(line, ref name) => write!(f, "line {}: undefined symbol {}", line, name),
+            Error::DuplicateSymbol(line, ref name) => write!(f, "line {}: {} is already defined", line, name),
+            Error::ValueOutOfRange(line, value) => write!(f, "line {}: {} is out of range", line, value),
+            Error::BranchOutOfRange(line, offset) =>
+                write!(f, "line {}: branch offset {} does not fit in a byte", line, offset),
+            Error::Arithmetic(line, ref expression) =>
+                write!(f, "line {}: `{}` overflows or divides by zero", line, expression)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub origin: u16,
+    pub bytes: Vec<u8>
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    pub symbols: HashMap<String, u16>
+}
+
+impl Program {
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// Pokes the program into memory, skipping addresses the bus does not
+    /// allow to be changed.
+    pub fn load_into<B: Bus>(&self, cpu: &mut Cpu<B>) {
+        for segment in &self.segments {
+            for (i, &byte) in segment.bytes.iter().enumerate() {
+                cpu.poke(segment.origin.wrapping_add(i as u16), byte);
+            }
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Program, Error> {
+    let mut assembler = Assembler::default();
+    for (index, line) in source.lines().enumerate() {
+        assembler.first_pass(index + 1, line)?;
+    }
+    assembler.second_pass()
+}
+
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(String),
+    Direct(String),
+    DirectX(String),
+    DirectY(String),
+    IndexedIndirect(String),
+    IndirectIndexed(String),
+    Indirect(String)
+}
+
+enum DataItem {
+    Expression(String),
+    Text(Vec<u8>)
+}
+
+enum StatementKind {
+    Org(u16),
+    Constant(String, String),
+    Data(usize, Vec<DataItem>),
+    Instruction(u8, OperandMode, Option<String>)
+}
+
+struct Statement {
+    line: usize,
+    pc: u16,
+    kind: StatementKind
+}
+
+#[derive(Default)]
+struct Assembler {
+    pc: u32,
+    symbols: HashMap<String, u16>,
+    statements: Vec<Statement>
+}
+
+impl Assembler {
+    fn define(&mut self, line: usize, name: &str, value: i64) -> Result<(), Error> {
+        if self.symbols.contains_key(name) {
+            return Err(Error::DuplicateSymbol(line, name.to_string()));
+        }
+        if !(0..=0xffff).contains(&value) {
+            return Err(Error::ValueOutOfRange(line, value));
+        }
+        self.symbols.insert(name.to_string(), value as u16);
+        Ok(())
+    }
+
+    fn current_pc(&self, line: usize) -> Result<u16, Error> {
+        if self.pc > 0xffff {
+            Err(Error::ValueOutOfRange(line, self.pc as i64))
+        }
+        else {
+            Ok(self.pc as u16)
+        }
+    }
+
+    fn push(&mut self, line: usize, size: usize, kind: StatementKind) -> Result<(), Error> {
+        let pc = self.current_pc(line)?;
+        self.statements.push(Statement { line, pc, kind });
+        self.pc += size as u32;
+        if self.pc > 0x10000 {
+            return Err(Error::ValueOutOfRange(line, self.pc as i64));
+        }
+        Ok(())
+    }
+
+    fn first_pass(&mut self, line: usize, text: &str) -> Result<(), Error> {
+        let mut rest = strip_comment(text).trim();
+        if let Some(end) = label_end(rest) {
+            let pc = self.current_pc(line)? as i64;
+            self.define(line, &rest[..end], pc)?;
+            rest = rest[end + 1..].trim();
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+        if let Some(eq) = rest.find('=') {
+            let name = rest[..eq].trim();
+            if is_identifier(name) {
+                let expression = rest[eq + 1..].trim().to_string();
+                let pc = self.current_pc(line)?;
+                match evaluate(&expression, &self.symbols, pc, line)? {
+                    Some(value) => self.define(line, name, value)?,
+                    None if self.symbols.contains_key(name) =>
+                        return Err(Error::DuplicateSymbol(line, name.to_string())),
+                    None => self.push(line, 0, StatementKind::Constant(name.to_string(), expression))?
+                }
+                return Ok(());
+            }
+        }
+        let (word, operand) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, "")
+        };
+        if let Some(directive) = word.strip_prefix('.') {
+            self.directive(line, &directive.to_lowercase(), operand)
+        }
+        else {
+            self.instruction(line, &word.to_uppercase(), operand)
+        }
+    }
+
+    fn directive(&mut self, line: usize, name: &str, operand: &str) -> Result<(), Error> {
+        match name {
+            "org" => {
+                let pc = self.current_pc(line)?;
+                let origin = evaluate(operand, &self.symbols, pc, line)?
+                    .ok_or_else(|| Error::UndefinedSymbol(line, operand.to_string()))?;
+                if !(0..=0xffff).contains(&origin) {
+                    return Err(Error::ValueOutOfRange(line, origin));
+                }
+                self.pc = origin as u32;
+                self.push(line, 0, StatementKind::Org(origin as u16))
+            },
+            "byte" | "word" => {
+                let width = if name == "byte" { 1 } else { 2 };
+                let mut items = Vec::new();
+                let mut size = 0;
+                for argument in split_arguments(operand) {
+                    if argument.starts_with('"') {
+                        if argument.len() < 2 || !argument.ends_with('"') || width != 1 {
+                            return Err(Error::Syntax(line, argument));
+                        }
+                        let text = argument.as_bytes()[1..argument.len() - 1].to_vec();
+                        size += text.len();
+                        items.push(DataItem::Text(text));
+                    }
+                    else if argument.is_empty() {
+                        return Err(Error::Syntax(line, operand.to_string()));
+                    }
+                    else {
+                        size += width;
+                        items.push(DataItem::Expression(argument));
+                    }
+                }
+                self.push(line, size, StatementKind::Data(width, items))
+            },
+            _ => Err(Error::Syntax(line, format!(".{}", name)))
+        }
+    }
+
+    fn instruction(&mut self, line: usize, mnemonic: &str, operand: &str) -> Result<(), Error> {
+        if !(0..256).any(|op| decode(op as u8).is_some_and(|i| i.mnemonic() == mnemonic)) {
+            return Err(Error::UnknownMnemonic(line, mnemonic.to_string()));
+        }
+        let invalid = || Error::InvalidOperand(line, operand.to_string());
+        let find = |mode| opcode_for(mnemonic, mode).map(|opcode| (opcode, mode));
+        let pc = self.current_pc(line)?;
+        let (opcode, mode, expression) = match parse_operand(operand) {
+            Operand::None => {
+                let found = find(OperandMode::Implied).or_else(|| find(OperandMode::Accumulator));
+                let (opcode, mode) = found.ok_or_else(invalid)?;
+                (opcode, mode, None)
+            },
+            Operand::Accumulator => {
+                match find(OperandMode::Accumulator) {
+                    Some((opcode, mode)) => (opcode, mode, None),
+                    None => self.sized(mnemonic, line, pc, operand.to_string(),
+                                       OperandMode::ZeroPage, OperandMode::Absolute)?
+                }
+            },
+            Operand::Immediate(e) => {
+                let (opcode, mode) = find(OperandMode::Immediate).ok_or_else(invalid)?;
+                (opcode, mode, Some(e))
+            },
+            Operand::Direct(e) => {
+                match find(OperandMode::Relative) {
+                    Some((opcode, mode)) => (opcode, mode, Some(e)),
+                    None => self.sized(mnemonic, line, pc, e, OperandMode::ZeroPage, OperandMode::Absolute)?
+                }
+            },
+            Operand::DirectX(e) =>
+                self.sized(mnemonic, line, pc, e, OperandMode::ZeroPageX, OperandMode::AbsoluteX)?,
+            Operand::DirectY(e) =>
+                self.sized(mnemonic, line, pc, e, OperandMode::ZeroPageY, OperandMode::AbsoluteY)?,
+            Operand::IndexedIndirect(e) => {
+                let (opcode, mode) = find(OperandMode::IndexedIndirect).ok_or_else(invalid)?;
+                (opcode, mode, Some(e))
+            },
+            Operand::IndirectIndexed(e) => {
+                let (opcode, mode) = find(OperandMode::IndirectIndexed).ok_or_else(invalid)?;
+                (opcode, mode, Some(e))
+            },
+            Operand::Indirect(e) => {
+                let (opcode, mode) = find(OperandMode::Indirect).ok_or_else(invalid)?;
+                (opcode, mode, Some(e))
+            }
+        };
+        self.push(line, 1 + mode.operand_length(), StatementKind::Instruction(opcode, mode, expression))
+    }
+
+    fn sized(&self, mnemonic: &str, line: usize, pc: u16, expression: String,
+             zero_page: OperandMode, absolute: OperandMode)
+        -> Result<(u8, OperandMode, Option<String>), Error>
+    {
+        let value = evaluate(&expression, &self.symbols, pc, line)?;
+        let fits_zero_page = value.is_some_and(|v| (0..=0xff).contains(&v));
+        let zp = opcode_for(mnemonic, zero_page);
+        let abs = opcode_for(mnemonic, absolute);
+        let chosen = match (zp, abs) {
+            (Some(opcode), _) if fits_zero_page => (opcode, zero_page),
+            (_, Some(opcode)) => (opcode, absolute),
+            (Some(opcode), None) => (opcode, zero_page),
+            (None, None) => return Err(Error::InvalidOperand(line, expression))
+        };
+        Ok((chosen.0, chosen.1, Some(expression)))
+    }
+
+    fn second_pass(mut self) -> Result<Program, Error> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let statements = ::std::mem::take(&mut self.statements);
+        for statement in statements {
+            let line = statement.line;
+            let pc = statement.pc;
+            let mut bytes = Vec::new();
+            match statement.kind {
+                StatementKind::Org(origin) => {
+                    segments.push(Segment { origin, bytes: Vec::new() });
+                    continue;
+                },
+                StatementKind::Constant(name, expression) => {
+                    let value = self.resolve(&expression, pc, line)?;
+                    self.define(line, &name, value)?;
+                    continue;
+                },
+                StatementKind::Data(width, items) => {
+                    for item in items {
+                        match item {
+                            DataItem::Text(text) => bytes.extend(text),
+                            DataItem::Expression(e) => {
+                                let value = self.resolve(&e, pc, line)?;
+                                if width == 1 {
+                                    bytes.push(to_byte(value, line)?);
+                                }
+                                else {
+                                    let word = to_word(value, line)?;
+                                    bytes.push(word as u8);
+                                    bytes.push((word >> 8) as u8);
+                                }
+                            }
+                        }
+                    }
+                },
+                StatementKind::Instruction(opcode, mode, expression) => {
+                    bytes.push(opcode);
+                    if let Some(e) = expression {
+                        let value = self.resolve(&e, pc, line)?;
+                        match mode {
+                            OperandMode::Relative => {
+                                let offset = value.saturating_sub(pc as i64 + 2);
+                                if !(-128..=127).contains(&offset) {
+                                    return Err(Error::BranchOutOfRange(line, offset));
+                                }
+                                bytes.push(offset as u8);
+                            },
+                            OperandMode::Immediate => bytes.push(to_byte(value, line)?),
+                            _ if mode.operand_length() == 1 => {
+                                if !(0..=0xff).contains(&value) {
+                                    return Err(Error::ValueOutOfRange(line, value));
+                                }
+                                bytes.push(value as u8);
+                            },
+                            _ => {
+                                if !(0..=0xffff).contains(&value) {
+                                    return Err(Error::ValueOutOfRange(line, value));
+                                }
+                                bytes.push(value as u8);
+                                bytes.push((value >> 8) as u8);
+                            }
+                        }
+                    }
+                }
+            }
+            let needs_segment = segments.last()
+                .is_none_or(|s| s.origin as usize + s.bytes.len() != pc as usize);
+            if needs_segment {
+                segments.push(Segment { origin: pc, bytes: Vec::new() });
+            }
+            segments.last_mut().unwrap().bytes.extend(bytes);
+        }
+        segments.retain(|s| !s.bytes.is_empty());
+        Ok(Program { segments, symbols: self.symbols })
+    }
+
+    fn resolve(&self, expression: &str, pc: u16, line: usize) -> Result<i64, Error> {
+        let mut parser = Parser::new(expression, &self.symbols, pc, line);
+        parser.strict = true;
+        parser.parse()?.ok_or_else(|| Error::UndefinedSymbol(line, expression.to_string()))
+    }
+}
+
+fn opcode_for(mnemonic: &str, mode: OperandMode) -> Option<u8> {
+    (0..256)
+        .map(|op| op as u8)
+        .find(|&op| decode(op).is_some_and(|i| i.mnemonic() == mnemonic && i.operand_mode() == mode))
+}
+
+fn to_byte(value: i64, line: usize) -> Result<u8, Error> {
+    if !(-128..=0xff).contains(&value) {
+        Err(Error::ValueOutOfRange(line, value))
+    }
+    else {
+        Ok(value as u8)
+    }
+}
+
+fn to_word(value: i64, line: usize) -> Result<u16, Error> {
+    if !(-32768..=0xffff).contains(&value) {
+        Err(Error::ValueOutOfRange(line, value))
+    }
+    else {
+        Ok(value as u16)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ';' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+fn label_end(line: &str) -> Option<usize> {
+    line.find(':').filter(|&end| is_identifier(&line[..end]))
+}
+
+fn split_arguments(operand: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut depth = 0;
+    for c in operand.chars() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                current.push(c);
+            },
+            None => match c {
+                '"' | '\'' => { quote = Some(c); current.push(c) },
+                '(' => { depth += 1; current.push(c) },
+                ')' => { depth -= 1; current.push(c) },
+                ',' if depth == 0 => arguments.push(::std::mem::take(&mut current)),
+                _ => current.push(c)
+            }
+        }
+    }
+    arguments.push(current);
+    arguments.into_iter().map(|a| a.trim().to_string()).collect()
+}
+
+fn strip_index<'a>(operand: &'a str, register: &str) -> Option<&'a str> {
+    let arguments = split_arguments(operand);
+    if arguments.len() == 2 && arguments[1].eq_ignore_ascii_case(register) {
+        let comma = operand.rfind(',').unwrap();
+        Some(operand[..comma].trim())
+    }
+    else {
+        None
+    }
+}
+
+fn closing_paren(operand: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in operand.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+fn parse_operand(operand: &str) -> Operand {
+    if operand.is_empty() {
+        return Operand::None;
+    }
+    if operand.eq_ignore_ascii_case("a") {
+        return Operand::Accumulator;
+    }
+    if let Some(immediate) = operand.strip_prefix('#') {
+        return Operand::Immediate(immediate.trim().to_string());
+    }
+    if operand.starts_with('(') {
+        if let Some(close) = closing_paren(operand) {
+            let inner = &operand[1..close];
+            let after = operand[close + 1..].trim();
+            if after.is_empty() {
+                return match strip_index(inner, "x") {
+                    Some(e) => Operand::IndexedIndirect(e.to_string()),
+                    None => Operand::Indirect(inner.trim().to_string())
+                };
+            }
+            if let Some(rest) = after.strip_prefix(',') {
+                if rest.trim().eq_ignore_ascii_case("y") {
+                    return Operand::IndirectIndexed(inner.trim().to_string());
+                }
+            }
+        }
+    }
+    if let Some(e) = strip_index(operand, "x") {
+        return Operand::DirectX(e.to_string());
+    }
+    if let Some(e) = strip_index(operand, "y") {
+        return Operand::DirectY(e.to_string());
+    }
+    Operand::Direct(operand.to_string())
+}
+
+fn evaluate(expression: &str, symbols: &HashMap<String, u16>, pc: u16, line: usize)
+    -> Result<Option<i64>, Error>
+{
+    Parser::new(expression, symbols, pc, line).parse()
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    symbols: &'a HashMap<String, u16>,
+    pc: u16,
+    line: usize,
+    strict: bool
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &str, symbols: &'a HashMap<String, u16>, pc: u16, line: usize) -> Self {
+        Parser {
+            chars: expression.chars().collect(),
+            position: 0,
+            symbols,
+            pc,
+            line,
+            strict: false
+        }
+    }
+
+    fn error(&self) -> Error {
+        Error::Syntax(self.line, self.chars.iter().collect())
+    }
+
+    fn arithmetic_error(&self) -> Error {
+        Error::Arithmetic(self.line, self.chars.iter().collect())
+    }
+
+    /// Applies `f` to two values, unless one is not known yet. `f` returns
+    /// `None` on overflow or division by zero.
+    fn combine<F>(&self, lhs: Option<i64>, rhs: Option<i64>, f: F) -> Result<Option<i64>, Error>
+        where F: Fn(i64, i64) -> Option<i64>
+    {
+        match (lhs, rhs) {
+            (Some(l), Some(r)) => f(l, r).map(Some).ok_or_else(|| self.arithmetic_error()),
+            _ => Ok(None)
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).cloned()
+    }
+
+    fn accept(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let matches = token.chars().enumerate()
+            .all(|(i, c)| self.chars.get(self.position + i) == Some(&c));
+        if matches {
+            self.position += token.len();
+        }
+        matches
+    }
+
+    fn parse(&mut self) -> Result<Option<i64>, Error> {
+        let value = self.binary(0)?;
+        if self.peek().is_some() {
+            return Err(self.error());
+        }
+        Ok(value)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Option<i64>, Error> {
+        const LEVELS: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for &op in LEVELS[level] {
+                if self.accept(op) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = match op {
+                        "|" => self.combine(lhs, rhs, |l, r| Some(l | r)),
+                        "^" => self.combine(lhs, rhs, |l, r| Some(l ^ r)),
+                        "&" => self.combine(lhs, rhs, |l, r| Some(l & r)),
+                        "<<" => self.combine(lhs, rhs, |l, r| Some(l << (r & 63))),
+                        ">>" => self.combine(lhs, rhs, |l, r| Some(l >> (r & 63))),
+                        "+" => self.combine(lhs, rhs, i64::checked_add),
+                        "-" => self.combine(lhs, rhs, i64::checked_sub),
+                        "*" => self.combine(lhs, rhs, i64::checked_mul),
+                        "/" => self.combine(lhs, rhs, i64::checked_div),
+                        _ => self.combine(lhs, rhs, i64::checked_rem)
+                    }?;
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Option<i64>, Error> {
+        if self.accept("-") {
+            match self.unary()? {
+                Some(v) => v.checked_neg().map(Some).ok_or_else(|| self.arithmetic_error()),
+                None => Ok(None)
+            }
+        }
+        else if self.accept("~") {
+            Ok(self.unary()?.map(|v| !v))
+        }
+        else if self.accept("<") {
+            Ok(self.unary()?.map(|v| v & 0xff))
+        }
+        else if self.accept(">") {
+            Ok(self.unary()?.map(|v| (v >> 8) & 0xff))
+        }
+        else {
+            self.primary()
+        }
+    }
+
+    fn take_while<F>(&mut self, f: F) -> String
+        where F: Fn(char) -> bool
+    {
+        let start = self.position;
+        while self.position < self.chars.len() && f(self.chars[self.position]) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn number(&mut self, radix: u32) -> Result<Option<i64>, Error> {
+        let digits = self.take_while(|c| c.is_digit(radix));
+        i64::from_str_radix(&digits, radix).map(Some).map_err(|_| self.error())
+    }
+
+    fn primary(&mut self) -> Result<Option<i64>, Error> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.binary(0)?;
+                if !self.accept(")") {
+                    return Err(self.error());
+                }
+                Ok(value)
+            },
+            Some('*') => {
+                self.position += 1;
+                Ok(Some(self.pc as i64))
+            },
+            Some('$') => {
+                self.position += 1;
+                self.number(16)
+            },
+            Some('%') => {
+                self.position += 1;
+                self.number(2)
+            },
+            Some('\'') => {
+                let c = self.chars.get(self.position + 1).cloned();
+                if self.chars.get(self.position + 2) != Some(&'\'') {
+                    return Err(self.error());
+                }
+                self.position += 3;
+                Ok(c.map(|c| c as i64))
+            },
+            Some(c) if c.is_ascii_digit() => self.number(10),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                match self.symbols.get(&name) {
+                    Some(&value) => Ok(Some(value as i64)),
+                    None if self.strict => Err(Error::UndefinedSymbol(self.line, name)),
+                    None => Ok(None)
+                }
+            },
+            _ => Err(self.error())
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Configures a `Cpu` in one place instead of poking at a default one:
+//!
+//! ```text
+//! let cpu = CpuBuilder::new()
+//!     .load(0x8000, &rom)
+//!     .reset()
+//!     .illegal_opcodes(IllegalOpcodePolicy::Halt)
+//!     .build();
+//! ```
+//!
+//! `CpuBuilder::with_bus` starts from a machine's own memory map instead of
+//! plain RAM; without the `std` feature it is the only way to start.
+
+use super::{Bus, Cpu, DefaultBus, IllegalOpcodePolicy, Registers};
+use super::dispatch::Variant;
+#[cfg(feature = "std")]
+use super::Ram;
+#[cfg(feature = "std")]
+use super::callstack::CallStack;
+#[cfg(feature = "std")]
+use super::cdl::CodeDataLogger;
+#[cfg(feature = "std")]
+use super::profiler::Profiler;
+#[cfg(feature = "std")]
+use super::trace::Tracer;
+
+pub struct CpuBuilder<B: Bus = DefaultBus> {
+    bus: B,
+    variant: Variant,
+    registers: Registers,
+    reset: bool,
+    #[cfg(feature = "std")]
+    tracer: Option<Tracer>,
+    #[cfg(feature = "std")]
+    logger: Option<CodeDataLogger>,
+    #[cfg(feature = "std")]
+    profiler: Option<Profiler>,
+    #[cfg(feature = "std")]
+    call_stack: Option<CallStack>,
+    illegal_opcode_policy: IllegalOpcodePolicy<B>
+}
+
+#[cfg(feature = "std")]
+impl CpuBuilder {
+    pub fn new() -> Self {
+        CpuBuilder::with_bus(Ram::new())
+    }
+
+    /// Copies `bytes` into RAM starting at `address`.
+    pub fn load(mut self, address: u16, bytes: &[u8]) -> Self {
+        let start = address as usize;
+        assert!(start + bytes.len() <= 0x10000, "loading would exceed the 64K mark");
+        self.bus[start..start + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CpuBuilder {
+    fn default() -> Self {
+        CpuBuilder::new()
+    }
+}
+
+impl<B: Bus> CpuBuilder<B> {
+    pub fn with_bus(bus: B) -> Self {
+        CpuBuilder {
+            bus,
+            variant: Variant::default(),
+            registers: Registers::default(),
+            reset: false,
+            #[cfg(feature = "std")]
+            tracer: None,
+            #[cfg(feature = "std")]
+            logger: None,
+            #[cfg(feature = "std")]
+            profiler: None,
+            #[cfg(feature = "std")]
+            call_stack: None,
+            illegal_opcode_policy: IllegalOpcodePolicy::default()
+        }
+    }
+
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    pub fn registers(mut self, registers: Registers) -> Self {
+        self.registers = registers;
+        self
+    }
+
+    pub fn pc(mut self, pc: u16) -> Self {
+        self.registers.pc = pc as usize;
+        self
+    }
+
+    pub fn a(mut self, a: u8) -> Self {
+        self.registers.a = a;
+        self
+    }
+
+    pub fn x(mut self, x: u8) -> Self {
+        self.registers.x = x;
+        self
+    }
+
+    pub fn y(mut self, y: u8) -> Self {
+        self.registers.y = y;
+        self
+    }
+
+    pub fn s(mut self, s: u8) -> Self {
+        self.registers.s = s as usize;
+        self
+    }
+
+    pub fn p(mut self, p: u8) -> Self {
+        self.registers.p = p.into();
+        self
+    }
+
+    /// Runs the hardware reset sequence when the CPU is built, so that it
+    /// starts at the reset vector with I set and S three below its initial
+    /// value, seven cycles in.
+    pub fn reset(mut self) -> Self {
+        self.reset = true;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub fn tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub fn logger(mut self, logger: CodeDataLogger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub fn profiler(mut self, profiler: Profiler) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_stack(mut self, call_stack: CallStack) -> Self {
+        self.call_stack = Some(call_stack);
+        self
+    }
+
+    pub fn illegal_opcodes(mut self, policy: IllegalOpcodePolicy<B>) -> Self {
+        self.illegal_opcode_policy = policy;
+        self
+    }
+
+    pub fn build(self) -> Cpu<B> {
+        let mut cpu = Cpu::with_bus(self.bus);
+        cpu.variant = self.variant;
+        cpu.handlers = self.variant.handlers();
+        cpu.registers = self.registers;
+        #[cfg(feature = "std")]
+        {
+            cpu.tracer = self.tracer;
+            cpu.logger = self.logger;
+            cpu.profiler = self.profiler;
+            cpu.call_stack = self.call_stack;
+        }
+        cpu.illegal_opcode_policy = self.illegal_opcode_policy;
+        if self.reset {
+            cpu.reset();
+        }
+        cpu
+    }
+}
@@ -0,0 +1,152 @@
+//! The memory map the CPU runs against. `Cpu` is generic over its `Bus`, so a
+//! machine's address decoding is compiled into the core instead of costing a
+//! dynamic call on every access; `Ram` is the flat 64K memory used by default.
+//! Without the `std` feature there is no `Ram`, and `Memory` runs the CPU
+//! against storage the caller provides instead.
+
+#[cfg(feature = "std")]
+use core::ops::{Deref, DerefMut};
+
+/// The bus of a `Cpu` or `CpuBuilder` that does not name one.
+#[cfg(feature = "std")]
+pub type DefaultBus = Ram;
+#[cfg(not(feature = "std"))]
+pub type DefaultBus = Memory<&'static mut [u8]>;
+
+pub trait Bus: 'static {
+    /// A read by the CPU, with whatever side effects the device at the
+    /// address has. `None` means nothing responds there.
+    fn read(&mut self, address: u16) -> Option<u8>;
+
+    /// A write by the CPU. Returns false if nothing responds at the address.
+    fn write(&mut self, address: u16, value: u8) -> bool;
+
+    /// Reads without side effects, for disassembly, tracing and debuggers.
+    /// `None` means nothing responds there.
+    fn peek(&self, address: u16) -> Option<u8>;
+
+    /// Changes memory without side effects, for debuggers and cheats. What a
+    /// poke does to registers of devices is up to the bus; returns false if
+    /// the address cannot be changed this way.
+    fn poke(&mut self, address: u16, value: u8) -> bool;
+
+    /// The bank an address currently maps to, for memory maps that switch
+    /// banks into the 64K address space.
+    fn bank(&self, _address: u16) -> u8 {
+        0
+    }
+
+    /// How many master clock cycles an access to `address` takes; on the
+    /// SNES that depends on the memory region. The CPU's timestamp advances
+    /// by it, so the default of one makes it count CPU cycles.
+    #[inline]
+    fn access_time(&self, _address: u16) -> u64 {
+        1
+    }
+
+    /// Tells the bus the CPU's master clock timestamp before each access, so
+    /// that a bus with other chips behind it can bring the ones an access
+    /// touches up to that moment first.
+    #[inline]
+    fn synchronize(&mut self, _timestamp: u64) {}
+}
+
+/// 64K of RAM covering the whole address space.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct Ram {
+    bytes: Vec<u8>
+}
+
+#[cfg(feature = "std")]
+impl Ram {
+    pub fn new() -> Self {
+        Ram { bytes: vec![0; 0x10000] }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Ram {
+    fn default() -> Self {
+        Ram::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Deref for Ram {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(feature = "std")]
+impl DerefMut for Ram {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+#[cfg(feature = "std")]
+impl Bus for Ram {
+    #[inline]
+    fn read(&mut self, address: u16) -> Option<u8> {
+        Some(self.bytes[address as usize])
+    }
+
+    #[inline]
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        self.bytes[address as usize] = value;
+        true
+    }
+
+    #[inline]
+    fn peek(&self, address: u16) -> Option<u8> {
+        Some(self.bytes[address as usize])
+    }
+
+    fn poke(&mut self, address: u16, value: u8) -> bool {
+        self.write(address, value)
+    }
+}
+
+/// RAM in storage owned by the caller, for targets without an allocator:
+/// an array, or a `&'static mut` to one in a `static`. The memory is mapped
+/// from address 0; whatever lies beyond its end is unmapped.
+///
+/// ```text
+/// static mut MEMORY: [u8; 0x10000] = [0; 0x10000];
+/// let bus = Memory(unsafe { &mut *addr_of_mut!(MEMORY) });
+/// let mut cpu = Cpu::with_bus(bus);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Memory<M>(pub M);
+
+impl<M: AsRef<[u8]> + AsMut<[u8]> + 'static> Bus for Memory<M> {
+    #[inline]
+    fn read(&mut self, address: u16) -> Option<u8> {
+        self.peek(address)
+    }
+
+    #[inline]
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        self.poke(address, value)
+    }
+
+    #[inline]
+    fn peek(&self, address: u16) -> Option<u8> {
+        self.0.as_ref().get(address as usize).cloned()
+    }
+
+    #[inline]
+    fn poke(&mut self, address: u16, value: u8) -> bool {
+        match self.0.as_mut().get_mut(address as usize) {
+            Some(byte) => {
+                *byte = value;
+                true
+            },
+            None => false
+        }
+    }
+}
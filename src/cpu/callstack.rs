@@ -0,0 +1,118 @@
+//! A shadow call stack, kept by a `Cpu` that was given one. The CPU pushes a
+//! frame whenever it executes JSR or BRK or takes an interrupt, and pops one
+//! on RTS and RTI. Programs that push return addresses themselves, discard
+//! them or jump through `RTS` tables make the real stack disagree with the
+//! shadow one; each such return is recorded as a `Mismatch`. Every frame
+//! remembers S, and a return drops all frames whose return address S has
+//! moved past, so that frames abandoned with PLA or TXS do not pile up.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use super::symbols::SymbolTable;
+
+const MAX_MISMATCHES: usize = 64;
+/// The stack has room for 128 return addresses; frames beyond that are left
+/// over from code that never returned, like a loop of BRKs without RTI.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Call,
+    Interrupt
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// Address of the JSR or BRK, or of the instruction that was interrupted.
+    pub call_site: u16,
+    pub target: u16,
+    /// Where RTS or RTI is expected to continue.
+    pub return_address: u16,
+    /// S after the return address was pushed.
+    pub s: u8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Address of the RTS or RTI.
+    pub pc: u16,
+    pub expected: Option<u16>,
+    pub actual: u16
+}
+
+#[derive(Debug, Default)]
+pub struct CallStack {
+    frames: VecDeque<Frame>,
+    mismatches: VecDeque<Mismatch>
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The frames, outermost first.
+    pub fn frames(&self) -> &VecDeque<Frame> {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The most recent unbalanced returns, oldest first.
+    pub fn mismatches(&self) -> &VecDeque<Mismatch> {
+        &self.mismatches
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.mismatches.clear();
+    }
+
+    pub(super) fn call(&mut self, kind: FrameKind, call_site: u16, target: u16, return_address: u16, s: u8) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Frame { kind, call_site, target, return_address, s });
+    }
+
+    /// A return at `pc` to `actual`, which left S at `s`.
+    pub(super) fn ret(&mut self, kind: FrameKind, pc: u16, actual: u16, s: u8) {
+        let top = self.frames.back().map(|frame| (frame.kind, frame.return_address));
+        if top == Some((kind, actual)) {
+            self.frames.pop_back();
+        }
+        else {
+            if self.mismatches.len() == MAX_MISMATCHES {
+                self.mismatches.pop_front();
+            }
+            self.mismatches.push_back(Mismatch { pc, expected: top.map(|(_, address)| address), actual });
+        }
+        // the frames whose return addresses were pulled or dropped are gone;
+        // a return to an address nobody called from leaves the others alone
+        while self.frames.back().is_some_and(|frame| frame.s < s) {
+            self.frames.pop_back();
+        }
+    }
+
+    /// Formats a backtrace, innermost frame first, with `pc` as the current
+    /// position in the innermost routine.
+    pub fn backtrace(&self, pc: u16, symbols: Option<&SymbolTable>) -> String {
+        let describe = |address: u16| match symbols.and_then(|s| s.describe(address)) {
+            Some(name) => format!("{:04X} {}", address, name),
+            None => format!("{:04X}", address)
+        };
+        let mut text = String::new();
+        let _ = writeln!(text, "#0  {}", describe(pc));
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            let note = match frame.kind {
+                FrameKind::Call => "",
+                FrameKind::Interrupt => "  <interrupted>"
+            };
+            let _ = writeln!(text, "#{:<2} {}{}", depth + 1, describe(frame.call_site), note);
+        }
+        text
+    }
+}
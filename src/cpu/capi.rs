@@ -0,0 +1,214 @@
+//! A C API, so that programs in other languages can embed the core. It is
+//! behind the `capi` feature, and the shared library is built with
+//!
+//! ```text
+//! cargo rustc --release --lib --features capi --crate-type cdylib
+//! ```
+//!
+//! The crate has no cdylib crate type of its own because that cannot be
+//! built without `std`. The declarations in `include/sfoxidized.h` are
+//! generated from this file by cbindgen, configured in `cbindgen.toml`.
+//! Builds with `capi` generate the header into OUT_DIR, and a test fails
+//! until the copy in `include` matches; the doc comments here are the
+//! header's documentation.
+//!
+//! The embedder supplies the bus as callbacks that receive an opaque user
+//! pointer. Functions that execute return one of the `SFOX_*` status codes;
+//! after a failure `sfox_cpu_error` describes what went wrong.
+//!
+//! The functions take references, so every pointer C passes in must be
+//! valid and not NULL; only `sfox_cpu_free` accepts NULL. Passing NULL
+//! anywhere else is undefined behaviour.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use super::{Bus, Cpu, ErrorKind, StatusRegister};
+use super::debugger::StopReason;
+
+/// Status codes returned by `sfox_cpu_step` and `sfox_cpu_run`.
+pub const SFOX_OK: c_int = 0;
+pub const SFOX_ILLEGAL_OPCODE: c_int = 1;
+pub const SFOX_UNIMPLEMENTED: c_int = 2;
+pub const SFOX_UNMAPPED: c_int = 3;
+pub const SFOX_HALTED: c_int = 4;
+
+/// The bus, `sfox_bus` in C. `read` and `write` are required. `peek` reads
+/// without side effects and is only used to describe errors; if it is
+/// NULL, the instructions listed in `sfox_cpu_error` show the open bus.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Callbacks {
+    pub user: *mut c_void,
+    pub read: Option<extern "C" fn(user: *mut c_void, address: u16) -> u8>,
+    pub write: Option<extern "C" fn(user: *mut c_void, address: u16, value: u8)>,
+    pub peek: Option<extern "C" fn(user: *mut c_void, address: u16) -> u8>
+}
+
+/// The registers, `sfox_registers` in C. P reads with bit 5 set and B
+/// clear, like a trace shows it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegisterFile {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub s: u8,
+    pub p: u8
+}
+
+pub struct CallbackBus {
+    user: *mut c_void,
+    read: extern "C" fn(*mut c_void, u16) -> u8,
+    write: extern "C" fn(*mut c_void, u16, u8),
+    peek: Option<extern "C" fn(*mut c_void, u16) -> u8>
+}
+
+impl Bus for CallbackBus {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        Some((self.read)(self.user, address))
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        (self.write)(self.user, address, value);
+        true
+    }
+
+    fn peek(&self, address: u16) -> Option<u8> {
+        self.peek.map(|peek| peek(self.user, address))
+    }
+
+    fn poke(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+}
+
+/// A CPU as handed out to C, `sfox_cpu`, which only sees a pointer to it.
+pub struct Handle {
+    cpu: Cpu<CallbackBus>,
+    error: Option<CString>
+}
+
+impl Handle {
+    fn status<T>(&mut self, result: Result<T, super::Error>) -> c_int {
+        let kind = match result {
+            Ok(_) => {
+                self.error = None;
+                return SFOX_OK;
+            },
+            Err(error) => {
+                // error messages hold no NUL, but never leave C without one
+                let message = CString::new(error.to_string())
+                    .unwrap_or_else(|_| CString::new("the CPU failed").unwrap());
+                self.error = Some(message);
+                error.kind
+            }
+        };
+        match kind {
+            ErrorKind::IllegalOpcode(_) => SFOX_ILLEGAL_OPCODE,
+            ErrorKind::Unimplemented(_) => SFOX_UNIMPLEMENTED,
+            ErrorKind::UnmappedAccess(..) => SFOX_UNMAPPED,
+            ErrorKind::Halt(_) => SFOX_HALTED
+        }
+    }
+}
+
+/// Creates a CPU on the given bus, with all registers zero; call
+/// `sfox_cpu_reset` to start from the reset vector. Returns NULL if `read`
+/// or `write` is missing. The callbacks are copied.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_new(callbacks: &Callbacks) -> Option<Box<Handle>> {
+    let bus = CallbackBus {
+        user: callbacks.user,
+        read: callbacks.read?,
+        write: callbacks.write?,
+        peek: callbacks.peek
+    };
+    Some(Box::new(Handle { cpu: Cpu::with_bus(bus), error: None }))
+}
+
+/// Destroys a CPU. Passing NULL does nothing.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_free(cpu: Option<Box<Handle>>) {
+    drop(cpu);
+}
+
+/// Runs the reset sequence, which loads PC from the reset vector.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_reset(cpu: &mut Handle) {
+    cpu.cpu.reset();
+}
+
+/// Executes one instruction, or services a pending interrupt.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_step(cpu: &mut Handle) -> c_int {
+    let result = cpu.cpu.step();
+    cpu.status(result)
+}
+
+/// Executes instructions until at least `cycles` cycles have passed. The
+/// last instruction may overshoot the budget; `sfox_cpu_cycles` tells by
+/// how much. Stops early with `SFOX_HALTED` when the CPU locks up.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_run(cpu: &mut Handle, cycles: u64) -> c_int {
+    match cpu.cpu.run(cycles) {
+        Ok(StopReason::Halted(_)) => {
+            cpu.error = None;
+            SFOX_HALTED
+        },
+        result => cpu.status(result)
+    }
+}
+
+/// The number of cycles executed since the CPU was created.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_cycles(cpu: &Handle) -> u64 {
+    cpu.cpu.cycles()
+}
+
+/// Drives the IRQ line. The interrupt is taken before the next
+/// instruction for as long as the line is active and I is clear.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_set_irq(cpu: &mut Handle, active: bool) {
+    cpu.cpu.set_irq(active);
+}
+
+/// Drives the NMI line. NMI is edge triggered: only a change from
+/// inactive to active causes an interrupt.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_set_nmi(cpu: &mut Handle, active: bool) {
+    cpu.cpu.set_nmi(active);
+}
+
+#[no_mangle]
+pub extern "C" fn sfox_cpu_get_registers(cpu: &Handle, registers: &mut RegisterFile) {
+    let r = cpu.cpu.registers();
+    *registers = RegisterFile {
+        pc: r.pc(),
+        a: r.a(),
+        x: r.x(),
+        y: r.y(),
+        s: r.s(),
+        p: r.p().to_byte(false)
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn sfox_cpu_set_registers(cpu: &mut Handle, registers: &RegisterFile) {
+    let r = cpu.cpu.registers_mut();
+    r.set_pc(registers.pc);
+    r.set_a(registers.a);
+    r.set_x(registers.x);
+    r.set_y(registers.y);
+    r.set_s(registers.s);
+    r.set_p(StatusRegister::from_byte(registers.p));
+}
+
+/// Describes why the last `sfox_cpu_step` or `sfox_cpu_run` failed, or
+/// returns NULL if it succeeded. The string belongs to the CPU and stays
+/// valid until the next step, run or free.
+#[no_mangle]
+pub extern "C" fn sfox_cpu_error(cpu: &Handle) -> *const c_char {
+    cpu.error.as_ref().map_or(ptr::null(), |error| error.as_ptr())
+}
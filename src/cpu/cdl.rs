@@ -0,0 +1,167 @@
+//! Code/data logging. While attached to a `Cpu`, the logger records for every
+//! address whether it was fetched as an opcode, fetched as an operand, read as
+//! data or written. Dummy bus cycles (the extra reads the 6502 performs while
+//! indexing or pushing) are not logged, so the map only reflects accesses the
+//! program actually asked for.
+//!
+//! The log is saved as a flat 64 KiB file holding one flag byte per address,
+//! which makes it trivial to merge the logs of several runs by or-ing them.
+
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+pub const SIZE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Opcode,
+    Operand,
+    Read,
+    Write
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage(pub u8);
+
+impl Usage {
+    pub const NONE: Usage = Usage(0);
+    pub const OPCODE: Usage = Usage(0x01);
+    pub const OPERAND: Usage = Usage(0x02);
+    pub const READ: Usage = Usage(0x04);
+    pub const WRITE: Usage = Usage(0x08);
+    pub const CODE: Usage = Usage(0x03);
+    pub const DATA: Usage = Usage(0x0c);
+
+    pub fn contains(self, other: Usage) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Usage) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn is_code(self) -> bool {
+        self.intersects(Usage::CODE)
+    }
+
+    pub fn is_data(self) -> bool {
+        self.intersects(Usage::DATA)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<AccessKind> for Usage {
+    fn from(kind: AccessKind) -> Self {
+        match kind {
+            AccessKind::Opcode => Usage::OPCODE,
+            AccessKind::Operand => Usage::OPERAND,
+            AccessKind::Read => Usage::READ,
+            AccessKind::Write => Usage::WRITE
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub total: usize,
+    pub code: usize,
+    pub data: usize,
+    pub unused: usize
+}
+
+pub struct CodeDataLogger {
+    usage: Vec<u8>
+}
+
+impl Default for CodeDataLogger {
+    fn default() -> Self {
+        CodeDataLogger { usage: vec![0; SIZE] }
+    }
+}
+
+impl CodeDataLogger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<CodeDataLogger> {
+        let usage = fs::read(path)?;
+        if usage.len() != SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("expected {} bytes, found {}", SIZE, usage.len())));
+        }
+        Ok(CodeDataLogger { usage })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, &self.usage)
+    }
+
+    pub fn log(&mut self, address: u16, kind: AccessKind) {
+        self.usage[address as usize] |= Usage::from(kind).0;
+    }
+
+    pub fn usage(&self, address: u16) -> Usage {
+        Usage(self.usage[address as usize])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.usage
+    }
+
+    pub fn merge(&mut self, other: &CodeDataLogger) {
+        for (mine, theirs) in self.usage.iter_mut().zip(&other.usage) {
+            *mine |= *theirs;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for usage in &mut self.usage {
+            *usage = 0;
+        }
+    }
+
+    pub fn coverage(&self, range: Range<usize>) -> Coverage {
+        let mut coverage = Coverage::default();
+        for &usage in &self.usage[range] {
+            let usage = Usage(usage);
+            coverage.total += 1;
+            if usage.is_code() {
+                coverage.code += 1;
+            }
+            if usage.is_data() {
+                coverage.data += 1;
+            }
+            if usage.is_empty() {
+                coverage.unused += 1;
+            }
+        }
+        coverage
+    }
+
+    /// Maximal runs of addresses within `range` that were never accessed.
+    pub fn unused_ranges(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+        let end = range.end;
+        for address in range {
+            match (self.usage[address] == 0, start) {
+                (true, None) => start = Some(address),
+                (false, Some(s)) => {
+                    ranges.push(s..address);
+                    start = None;
+                },
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            ranges.push(s..end);
+        }
+        ranges
+    }
+}
@@ -0,0 +1,117 @@
+//! Breakpoints and watchpoints. The CPU consults its `Debugger` before each
+//! instruction in `Cpu::run` and on every bus access, and reports a hit as the
+//! `StopReason` returned from `run`, `step_over` and `step_out`. The
+//! `Debugger` itself needs the `std` feature.
+
+#[cfg(feature = "std")]
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub on_read: bool,
+    pub on_write: bool
+}
+
+impl Watchpoint {
+    pub fn read(start: u16, end: u16) -> Self {
+        Watchpoint { start, end, on_read: true, on_write: false }
+    }
+
+    pub fn write(start: u16, end: u16) -> Self {
+        Watchpoint { start, end, on_read: false, on_write: true }
+    }
+
+    pub fn access(start: u16, end: u16) -> Self {
+        Watchpoint { start, end, on_read: true, on_write: true }
+    }
+
+    #[cfg(feature = "std")]
+    fn matches(&self, address: u16, access: Access) -> bool {
+        let wanted = match access {
+            Access::Read => self.on_read,
+            Access::Write => self.on_write
+        };
+        wanted && address >= self.start && address <= self.end
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    CyclesElapsed,
+    StepComplete,
+    Breakpoint(u16),
+    /// The processor locked up on an illegal opcode at this address.
+    Halted(u16),
+    Watchpoint { pc: u16, address: u16, access: Access, value: u8 }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    hit: Option<StopReason>
+}
+
+#[cfg(feature = "std")]
+impl Debugger {
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().cloned()
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.hit = None;
+    }
+
+    pub(super) fn check_access(&mut self, pc: u16, address: u16, access: Access, value: u8) {
+        if self.hit.is_none() && self.watchpoints.iter().any(|w| w.matches(address, access)) {
+            self.hit = Some(StopReason::Watchpoint { pc, address, access, value });
+        }
+    }
+
+    pub(super) fn take_hit(&mut self) -> Option<StopReason> {
+        self.hit.take()
+    }
+
+    pub(super) fn is_watching(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+}
@@ -0,0 +1,94 @@
+use super::instruction::OperandMode;
+use super::metadata::{opcode_info, OpcodeInfo};
+use super::symbols::SymbolTable;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub info: Option<&'static OpcodeInfo>,
+    pub text: String
+}
+
+impl Line {
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+
+    pub fn operand(&self) -> u16 {
+        match self.bytes.len() {
+            2 => self.bytes[1] as u16,
+            3 => self.bytes[1] as u16 | ((self.bytes[2] as u16) << 8),
+            _ => 0
+        }
+    }
+
+    pub fn branch_target(&self) -> u16 {
+        let offset = self.bytes.get(1).cloned().unwrap_or(0) as i8;
+        self.next_address().wrapping_add(offset as u16)
+    }
+}
+
+pub fn disassemble<F>(peek: F, address: u16) -> Line
+    where F: Fn(u16) -> u8
+{
+    disassemble_with_symbols(peek, address, None)
+}
+
+fn symbolic(symbols: Option<&SymbolTable>, address: u16, width: usize) -> String {
+    match symbols.and_then(|s| s.name_at(address)) {
+        Some(name) => name.to_string(),
+        None => format!("${:0w$X}", address, w = width)
+    }
+}
+
+pub fn disassemble_with_symbols<F>(peek: F, address: u16, symbols: Option<&SymbolTable>) -> Line
+    where F: Fn(u16) -> u8
+{
+    let opcode = peek(address);
+    let info = match opcode_info(opcode) {
+        Some(info) => info,
+        None => return Line {
+            address,
+            bytes: vec![opcode],
+            info: None,
+            text: format!(".byte ${:02X}", opcode)
+        }
+    };
+    let bytes: Vec<u8> = (0..info.length as u16)
+        .map(|i| peek(address.wrapping_add(i)))
+        .collect();
+    let mut line = Line { address, bytes, info: Some(info), text: String::new() };
+    let mnemonic = info.instruction.mnemonic();
+    let byte = symbolic(symbols, line.operand(), 2);
+    let word = symbolic(symbols, line.operand(), 4);
+    line.text = match info.instruction.operand_mode() {
+        OperandMode::Implied => mnemonic.to_string(),
+        OperandMode::Accumulator => format!("{} A", mnemonic),
+        OperandMode::Immediate => format!("{} #${:02X}", mnemonic, line.operand()),
+        OperandMode::ZeroPage => format!("{} {}", mnemonic, byte),
+        OperandMode::ZeroPageX => format!("{} {},X", mnemonic, byte),
+        OperandMode::ZeroPageY => format!("{} {},Y", mnemonic, byte),
+        OperandMode::Absolute => format!("{} {}", mnemonic, word),
+        OperandMode::AbsoluteX => format!("{} {},X", mnemonic, word),
+        OperandMode::AbsoluteY => format!("{} {},Y", mnemonic, word),
+        OperandMode::IndexedIndirect => format!("{} ({},X)", mnemonic, byte),
+        OperandMode::IndirectIndexed => format!("{} ({}),Y", mnemonic, byte),
+        OperandMode::Indirect => format!("{} ({})", mnemonic, word),
+        OperandMode::Relative => format!("{} {}", mnemonic, symbolic(symbols, line.branch_target(), 4))
+    };
+    line
+}
+
+pub fn disassemble_range<F>(peek: F, from: u16, count: usize) -> Vec<Line>
+    where F: Fn(u16) -> u8
+{
+    let mut lines = Vec::with_capacity(count);
+    let mut address = from;
+    for _ in 0..count {
+        let line = disassemble(&peek, address);
+        address = line.next_address();
+        lines.push(line);
+    }
+    lines
+}
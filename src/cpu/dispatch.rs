@@ -0,0 +1,115 @@
+//! Precomputed opcode handler tables. Every entry is `execute` specialised for
+//! one opcode and bus, so `decode` runs at compile time and executing a cycle
+//! of an instruction is a single indexed call.
+
+use super::{Bus, Cpu, ErrorKind};
+use core::marker::PhantomData;
+use super::instruction::*;
+
+/// Runs the next bus cycle of an instruction, `Cpu::t_state` tells which,
+/// or with `to_completion` all of its remaining cycles. Returns true once the
+/// instruction is complete.
+pub type Handler<B> = fn(&mut Cpu<B>, to_completion: bool) -> Result<bool, ErrorKind>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Variant {
+    #[default]
+    Nmos6502
+}
+
+impl Variant {
+    pub fn handlers<B: Bus>(self) -> &'static [Handler<B>; 256] {
+        match self {
+            Variant::Nmos6502 => &Tables::<B>::NMOS6502
+        }
+    }
+
+    /// Whether the variant knows `opcode`, which the CPU needs to know in the
+    /// cycle that fetches it.
+    pub fn is_documented(self, opcode: u8) -> bool {
+        match self {
+            Variant::Nmos6502 => NMOS6502_DOCUMENTED[opcode as usize]
+        }
+    }
+}
+
+static NMOS6502_DOCUMENTED: [bool; 256] = {
+    let mut documented = [false; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        documented[opcode] = decode(opcode as u8).is_some();
+        opcode += 1;
+    }
+    documented
+};
+
+fn execute<B: Bus, const OPCODE: u8>(cpu: &mut Cpu<B>, to_completion: bool) -> Result<bool, ErrorKind> {
+    // stepping a whole instruction this way leaves one indirect call per
+    // instruction, and lets the compiler see its cycles in sequence
+    loop {
+        let complete = cycle::<B, OPCODE>(cpu)?;
+        if complete || !to_completion {
+            return Ok(complete);
+        }
+        cpu.t_state += 1;
+    }
+}
+
+#[inline(always)]
+fn cycle<B: Bus, const OPCODE: u8>(cpu: &mut Cpu<B>) -> Result<bool, ErrorKind> {
+    Ok(match const { decode(OPCODE) } {
+        Some(Instruction::SingleByte(mnemonic)) => cpu.execute_single_byte(mnemonic),
+        Some(Instruction::Read(mnemonic, am)) => cpu.execute_read(mnemonic, am),
+        Some(Instruction::Store(mnemonic, am)) => cpu.execute_store(mnemonic, am),
+        Some(Instruction::ReadModifyWrite(mnemonic, am)) => cpu.execute_read_modify_write(mnemonic, am),
+        Some(Instruction::Push(mnemonic)) => cpu.execute_push(mnemonic),
+        Some(Instruction::Pull(mnemonic)) => cpu.execute_pull(mnemonic),
+        Some(Instruction::Branch(mnemonic)) => cpu.execute_branch(mnemonic),
+        Some(Instruction::Jump(am)) => cpu.execute_jump(am),
+        Some(Instruction::Misc(MiscMnemonic::JSR)) => cpu.execute_jsr(),
+        Some(Instruction::Misc(MiscMnemonic::RTS)) => cpu.execute_rts(),
+        Some(Instruction::Misc(MiscMnemonic::BRK)) => cpu.execute_brk(),
+        Some(Instruction::Misc(MiscMnemonic::RTI)) => cpu.execute_rti(),
+        // the CPU turns opcodes its variant does not document away before
+        // dispatch, so only an entry without an executor gets here
+        None => return Err(ErrorKind::Unimplemented(OPCODE))
+    })
+}
+
+/// The error for an opcode the variant does not document: the undocumented
+/// NMOS opcodes that stop the processor (KIL/JAM) halt, all others are
+/// illegal.
+pub const fn illegal(opcode: u8) -> ErrorKind {
+    if opcode & 0x0f == 0x02 && (opcode < 0x80 || opcode & 0x10 != 0) {
+        ErrorKind::Halt(opcode)
+    }
+    else {
+        ErrorKind::IllegalOpcode(opcode)
+    }
+}
+
+macro_rules! handlers {
+    ($($row:literal),*) => {
+        [$(
+            execute::<B, { $row + 0x0 }>, execute::<B, { $row + 0x1 }>,
+            execute::<B, { $row + 0x2 }>, execute::<B, { $row + 0x3 }>,
+            execute::<B, { $row + 0x4 }>, execute::<B, { $row + 0x5 }>,
+            execute::<B, { $row + 0x6 }>, execute::<B, { $row + 0x7 }>,
+            execute::<B, { $row + 0x8 }>, execute::<B, { $row + 0x9 }>,
+            execute::<B, { $row + 0xa }>, execute::<B, { $row + 0xb }>,
+            execute::<B, { $row + 0xc }>, execute::<B, { $row + 0xd }>,
+            execute::<B, { $row + 0xe }>, execute::<B, { $row + 0xf }>
+        ),*]
+    }
+}
+
+// statics cannot be generic, but references to associated constants are
+// promoted to one per bus type
+struct Tables<B>(PhantomData<B>);
+
+impl<B: Bus> Tables<B> {
+    const NMOS6502: [Handler<B>; 256] = handlers!(
+        0x00, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70,
+        0x80, 0x90, 0xa0, 0xb0, 0xc0, 0xd0, 0xe0, 0xf0
+    );
+}
@@ -0,0 +1,74 @@
+//! Errors raised while executing. Every error records the state of the CPU at
+//! the faulting instruction, together with the last few instructions that led
+//! there, so that it can be reported usefully without a debugger attached.
+//! The history needs the `std` feature.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+use super::Registers;
+use super::debugger::Access;
+
+/// How many of the most recently executed instructions an error keeps.
+pub const HISTORY_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An opcode the selected variant does not document.
+    IllegalOpcode(u8),
+    /// A documented opcode without an executor in the selected variant.
+    Unimplemented(u8),
+    /// An access to an address that nothing on the bus responds to.
+    UnmappedAccess(u16, Access),
+    /// One of the NMOS opcodes that lock up the processor until reset.
+    Halt(u8)
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::IllegalOpcode(opcode) => write!(f, "illegal opcode ${:02X}", opcode),
+            ErrorKind::Unimplemented(opcode) => write!(f, "unimplemented opcode ${:02X}", opcode),
+            ErrorKind::UnmappedAccess(address, Access::Read) => write!(f, "read from unmapped ${:04X}", address),
+            ErrorKind::UnmappedAccess(address, Access::Write) => write!(f, "write to unmapped ${:04X}", address),
+            ErrorKind::Halt(opcode) => write!(f, "processor halted by ${:02X}", opcode)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Address of the faulting instruction.
+    pub pc: u16,
+    /// Bank the instruction was fetched from; always 0 for flat memory.
+    pub bank: u8,
+    /// The registers at the time the error was raised.
+    pub registers: Registers,
+    pub cycles: u64,
+    /// Address and disassembly of the last instructions, oldest first and
+    /// ending with the faulting one.
+    #[cfg(feature = "std")]
+    pub history: Vec<(u16, String)>
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at ", self.kind)?;
+        if self.bank != 0 {
+            write!(f, "${:02X}:", self.bank)?;
+        }
+        let r = &self.registers;
+        // as in traces, P without the B flag that only exists on the stack
+        write!(f, "${:04X}\n  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+               self.pc, r.a(), r.x(), r.y(), r.p().to_byte(false), r.s(), self.cycles)?;
+        #[cfg(feature = "std")]
+        for &(address, ref text) in &self.history {
+            write!(f, "\n  {:04X}  {}", address, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Error {}
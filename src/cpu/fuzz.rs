@@ -0,0 +1,569 @@
+//! Differential fuzzing of `Cpu::step` against a deliberately naive
+//! reference interpreter. Each case starts both from the same random
+//! registers and memory, executes one random documented instruction and
+//! compares registers, flags, memory and the number of cycles taken.
+//! Undocumented opcodes must be rejected.
+//!
+//! The reference shares nothing with the CPU core: it has its own opcode
+//! table and computes cycle counts from the documented timings instead of
+//! counting bus accesses.
+
+use std::fmt;
+use super::{Cpu, CpuBuilder, Registers};
+
+/// A xorshift64* generator, good enough to produce test cases and small
+/// enough to not need a crate.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let value = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    IndirectX,
+    IndirectY,
+    Indirect,
+    Relative
+}
+
+// opcode, mnemonic, mode and cycles of every documented NMOS instruction,
+// as listed in the MOS programming manual
+const TABLE: &str = "
+69 ADC imm 2  65 ADC zp 3  75 ADC zpx 4  6d ADC abs 4  7d ADC abx 4  79 ADC aby 4  61 ADC izx 6  71 ADC izy 5
+29 AND imm 2  25 AND zp 3  35 AND zpx 4  2d AND abs 4  3d AND abx 4  39 AND aby 4  21 AND izx 6  31 AND izy 5
+c9 CMP imm 2  c5 CMP zp 3  d5 CMP zpx 4  cd CMP abs 4  dd CMP abx 4  d9 CMP aby 4  c1 CMP izx 6  d1 CMP izy 5
+49 EOR imm 2  45 EOR zp 3  55 EOR zpx 4  4d EOR abs 4  5d EOR abx 4  59 EOR aby 4  41 EOR izx 6  51 EOR izy 5
+a9 LDA imm 2  a5 LDA zp 3  b5 LDA zpx 4  ad LDA abs 4  bd LDA abx 4  b9 LDA aby 4  a1 LDA izx 6  b1 LDA izy 5
+09 ORA imm 2  05 ORA zp 3  15 ORA zpx 4  0d ORA abs 4  1d ORA abx 4  19 ORA aby 4  01 ORA izx 6  11 ORA izy 5
+e9 SBC imm 2  e5 SBC zp 3  f5 SBC zpx 4  ed SBC abs 4  fd SBC abx 4  f9 SBC aby 4  e1 SBC izx 6  f1 SBC izy 5
+85 STA zp 3   95 STA zpx 4  8d STA abs 4  9d STA abx 5  99 STA aby 5  81 STA izx 6  91 STA izy 6
+a2 LDX imm 2  a6 LDX zp 3  b6 LDX zpy 4  ae LDX abs 4  be LDX aby 4
+a0 LDY imm 2  a4 LDY zp 3  b4 LDY zpx 4  ac LDY abs 4  bc LDY abx 4
+86 STX zp 3   96 STX zpy 4  8e STX abs 4
+84 STY zp 3   94 STY zpx 4  8c STY abs 4
+e0 CPX imm 2  e4 CPX zp 3  ec CPX abs 4
+c0 CPY imm 2  c4 CPY zp 3  cc CPY abs 4
+24 BIT zp 3   2c BIT abs 4
+0a ASL acc 2  06 ASL zp 5  16 ASL zpx 6  0e ASL abs 6  1e ASL abx 7
+4a LSR acc 2  46 LSR zp 5  56 LSR zpx 6  4e LSR abs 6  5e LSR abx 7
+2a ROL acc 2  26 ROL zp 5  36 ROL zpx 6  2e ROL abs 6  3e ROL abx 7
+6a ROR acc 2  66 ROR zp 5  76 ROR zpx 6  6e ROR abs 6  7e ROR abx 7
+e6 INC zp 5   f6 INC zpx 6  ee INC abs 6  fe INC abx 7
+c6 DEC zp 5   d6 DEC zpx 6  ce DEC abs 6  de DEC abx 7
+90 BCC rel 2  b0 BCS rel 2  f0 BEQ rel 2  30 BMI rel 2  d0 BNE rel 2  10 BPL rel 2  50 BVC rel 2  70 BVS rel 2
+18 CLC imp 2  d8 CLD imp 2  58 CLI imp 2  b8 CLV imp 2  38 SEC imp 2  f8 SED imp 2  78 SEI imp 2
+ca DEX imp 2  88 DEY imp 2  e8 INX imp 2  c8 INY imp 2  ea NOP imp 2
+aa TAX imp 2  a8 TAY imp 2  ba TSX imp 2  8a TXA imp 2  9a TXS imp 2  98 TYA imp 2
+48 PHA imp 3  08 PHP imp 3  68 PLA imp 4  28 PLP imp 4
+4c JMP abs 3  6c JMP ind 5  20 JSR abs 6  60 RTS imp 6  40 RTI imp 6  00 BRK imp 7
+";
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    mnemonic: &'static str,
+    mode: Mode,
+    cycles: u64
+}
+
+fn table() -> Vec<Option<Entry>> {
+    let mut table = vec![None; 256];
+    let words: Vec<&'static str> = TABLE.split_whitespace().collect();
+    for entry in words.chunks(4) {
+        let opcode = u8::from_str_radix(entry[0], 16).unwrap();
+        let mode = match entry[2] {
+            "imp" => Mode::Implied,
+            "acc" => Mode::Accumulator,
+            "imm" => Mode::Immediate,
+            "zp" => Mode::ZeroPage,
+            "zpx" => Mode::ZeroPageX,
+            "zpy" => Mode::ZeroPageY,
+            "abs" => Mode::Absolute,
+            "abx" => Mode::AbsoluteX,
+            "aby" => Mode::AbsoluteY,
+            "izx" => Mode::IndirectX,
+            "izy" => Mode::IndirectY,
+            "ind" => Mode::Indirect,
+            "rel" => Mode::Relative,
+            mode => panic!("unknown mode {}", mode)
+        };
+        table[opcode as usize] = Some(Entry { mnemonic: entry[1], mode, cycles: entry[3].parse().unwrap() });
+    }
+    table
+}
+
+const C: u8 = 0x01;
+const Z: u8 = 0x02;
+const I: u8 = 0x04;
+const D: u8 = 0x08;
+const B: u8 = 0x10;
+const U: u8 = 0x20;
+const V: u8 = 0x40;
+const N: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct State {
+    pub pc: u16,
+    pub s: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    /// Always has bits 4 and 5 set, as PHP would push it.
+    pub p: u8
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+               self.pc, self.a, self.x, self.y, self.p, self.s)
+    }
+}
+
+pub struct Reference {
+    pub state: State,
+    pub memory: Vec<u8>,
+    pub cycles: u64,
+    table: Vec<Option<Entry>>
+}
+
+impl Reference {
+    pub fn new() -> Self {
+        Reference {
+            state: State { pc: 0, s: 0, a: 0, x: 0, y: 0, p: U | B },
+            memory: vec![0; 0x10000],
+            cycles: 0,
+            table: table()
+        }
+    }
+
+    pub fn is_documented(&self, opcode: u8) -> bool {
+        self.table[opcode as usize].is_some()
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn read_word_in_page(&self, address: u16) -> u16 {
+        let high = (address & 0xff00) | (address.wrapping_add(1) & 0x00ff);
+        self.read(address) as u16 | (self.read(high) as u16) << 8
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.state.pc);
+        self.state.pc = self.state.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let low = self.fetch() as u16;
+        low | (self.fetch() as u16) << 8
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(0x100 | self.state.s as u16, value);
+        self.state.s = self.state.s.wrapping_sub(1);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.state.s = self.state.s.wrapping_add(1);
+        self.read(0x100 | self.state.s as u16)
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.state.p & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.state.p |= flag;
+        }
+        else {
+            self.state.p &= !flag;
+        }
+    }
+
+    fn nz(&mut self, value: u8) -> u8 {
+        self.set_flag(Z, value == 0);
+        self.set_flag(N, value & 0x80 != 0);
+        value
+    }
+
+    /// The effective address and whether indexing crossed a page.
+    fn address(&mut self, mode: Mode) -> (u16, bool) {
+        let indexed = |base: u16, index: u8| {
+            let address = base.wrapping_add(index as u16);
+            (address, address & 0xff00 != base & 0xff00)
+        };
+        match mode {
+            Mode::ZeroPage => (self.fetch() as u16, false),
+            Mode::ZeroPageX => (self.fetch().wrapping_add(self.state.x) as u16, false),
+            Mode::ZeroPageY => (self.fetch().wrapping_add(self.state.y) as u16, false),
+            Mode::Absolute => (self.fetch_word(), false),
+            Mode::AbsoluteX => {
+                let base = self.fetch_word();
+                indexed(base, self.state.x)
+            },
+            Mode::AbsoluteY => {
+                let base = self.fetch_word();
+                indexed(base, self.state.y)
+            },
+            Mode::IndirectX => {
+                let pointer = self.fetch().wrapping_add(self.state.x);
+                (self.read_word_in_page(pointer as u16), false)
+            },
+            Mode::IndirectY => {
+                let pointer = self.fetch();
+                let base = self.read_word_in_page(pointer as u16);
+                indexed(base, self.state.y)
+            },
+            Mode::Indirect => {
+                let pointer = self.fetch_word();
+                (self.read_word_in_page(pointer), false)
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn adc(&mut self, m: u8) {
+        let a = self.state.a as u16;
+        let c = self.flag(C) as u16;
+        let sum = a + m as u16 + c;
+        if !self.flag(D) {
+            self.set_flag(C, sum > 0xff);
+            self.set_flag(V, (a as u8 ^ sum as u8) & (m ^ sum as u8) & 0x80 != 0);
+            self.state.a = self.nz(sum as u8);
+            return;
+        }
+        let mut low = (a & 0x0f) + (m as u16 & 0x0f) + c;
+        let mut high = (a >> 4) + (m as u16 >> 4);
+        if low > 9 {
+            low += 6;
+            high += 1;
+        }
+        // NMOS: Z from the binary sum, N and V before the high digit is fixed
+        self.set_flag(Z, sum & 0xff == 0);
+        self.set_flag(N, high & 0x08 != 0);
+        self.set_flag(V, (high << 4 ^ a) & 0x80 != 0 && (a ^ m as u16) & 0x80 == 0);
+        if high > 9 {
+            high += 6;
+        }
+        self.set_flag(C, high > 15);
+        self.state.a = ((high << 4) | (low & 0x0f)) as u8;
+    }
+
+    fn sbc(&mut self, m: u8) {
+        let a = self.state.a as i16;
+        let borrow = !self.flag(C) as i16;
+        let difference = a - m as i16 - borrow;
+        self.set_flag(C, difference >= 0);
+        self.set_flag(V, (a as u8 ^ m) & (a as u8 ^ difference as u8) & 0x80 != 0);
+        self.nz(difference as u8);
+        if !self.flag(D) {
+            self.state.a = difference as u8;
+            return;
+        }
+        let mut low = (a & 0x0f) - (m as i16 & 0x0f) - borrow;
+        let mut high = (a >> 4) - (m as i16 >> 4);
+        if low < 0 {
+            low -= 6;
+            high -= 1;
+        }
+        if high < 0 {
+            high -= 6;
+        }
+        self.state.a = ((high << 4) | (low & 0x0f)) as u8;
+    }
+
+    fn compare(&mut self, register: u8, m: u8) {
+        self.set_flag(C, register >= m);
+        self.nz(register.wrapping_sub(m));
+    }
+
+    fn shift(&mut self, mnemonic: &str, value: u8) -> u8 {
+        let carry = self.flag(C) as u8;
+        let (result, carry_out) = match mnemonic {
+            "ASL" => (value << 1, value & 0x80 != 0),
+            "LSR" => (value >> 1, value & 0x01 != 0),
+            "ROL" => (value << 1 | carry, value & 0x80 != 0),
+            _ => (value >> 1 | carry << 7, value & 0x01 != 0)
+        };
+        self.set_flag(C, carry_out);
+        self.nz(result)
+    }
+
+    /// Executes one instruction, returning `None` for undocumented opcodes.
+    pub fn step(&mut self) -> Option<()> {
+        let opcode = self.fetch();
+        let entry = self.table[opcode as usize]?;
+        self.cycles += entry.cycles;
+        let mode = entry.mode;
+        let (address, crossed) = match mode {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative => (0, false),
+            // JSR pushes before fetching its high byte, see below
+            _ if entry.mnemonic == "JSR" => (0, false),
+            _ => self.address(mode)
+        };
+        let operand = |this: &mut Self| match mode {
+            Mode::Immediate => this.fetch(),
+            Mode::Accumulator => this.state.a,
+            _ => this.read(address)
+        };
+        let mut page_penalty = crossed;
+        match entry.mnemonic {
+            "ADC" => { let m = operand(self); self.adc(m) },
+            "SBC" => { let m = operand(self); self.sbc(m) },
+            "AND" => { let m = operand(self); self.state.a = self.nz(self.state.a & m) },
+            "ORA" => { let m = operand(self); self.state.a = self.nz(self.state.a | m) },
+            "EOR" => { let m = operand(self); self.state.a = self.nz(self.state.a ^ m) },
+            "LDA" => { let m = operand(self); self.state.a = self.nz(m) },
+            "LDX" => { let m = operand(self); self.state.x = self.nz(m) },
+            "LDY" => { let m = operand(self); self.state.y = self.nz(m) },
+            "CMP" => { let m = operand(self); let a = self.state.a; self.compare(a, m) },
+            "CPX" => { let m = operand(self); let x = self.state.x; self.compare(x, m) },
+            "CPY" => { let m = operand(self); let y = self.state.y; self.compare(y, m) },
+            "BIT" => {
+                let m = operand(self);
+                self.set_flag(Z, self.state.a & m == 0);
+                self.set_flag(N, m & 0x80 != 0);
+                self.set_flag(V, m & 0x40 != 0);
+            },
+            "STA" => { page_penalty = false; let a = self.state.a; self.write(address, a) },
+            "STX" => { let x = self.state.x; self.write(address, x) },
+            "STY" => { let y = self.state.y; self.write(address, y) },
+            "ASL" | "LSR" | "ROL" | "ROR" => {
+                page_penalty = false;
+                let value = operand(self);
+                let result = self.shift(entry.mnemonic, value);
+                if mode == Mode::Accumulator {
+                    self.state.a = result;
+                }
+                else {
+                    self.write(address, result);
+                }
+            },
+            "INC" | "DEC" => {
+                page_penalty = false;
+                let value = operand(self);
+                let result = if entry.mnemonic == "INC" { value.wrapping_add(1) } else { value.wrapping_sub(1) };
+                let result = self.nz(result);
+                self.write(address, result);
+            },
+            "BCC" | "BCS" | "BEQ" | "BMI" | "BNE" | "BPL" | "BVC" | "BVS" => {
+                let offset = self.fetch() as i8;
+                let (flag, wanted) = match entry.mnemonic {
+                    "BCC" => (C, false), "BCS" => (C, true),
+                    "BNE" => (Z, false), "BEQ" => (Z, true),
+                    "BPL" => (N, false), "BMI" => (N, true),
+                    "BVC" => (V, false), _ => (V, true)
+                };
+                if self.flag(flag) == wanted {
+                    let target = self.state.pc.wrapping_add(offset as u16);
+                    self.cycles += if target & 0xff00 != self.state.pc & 0xff00 { 2 } else { 1 };
+                    self.state.pc = target;
+                }
+            },
+            "CLC" => self.set_flag(C, false),
+            "CLD" => self.set_flag(D, false),
+            "CLI" => self.set_flag(I, false),
+            "CLV" => self.set_flag(V, false),
+            "SEC" => self.set_flag(C, true),
+            "SED" => self.set_flag(D, true),
+            "SEI" => self.set_flag(I, true),
+            "DEX" => self.state.x = self.nz(self.state.x.wrapping_sub(1)),
+            "DEY" => self.state.y = self.nz(self.state.y.wrapping_sub(1)),
+            "INX" => self.state.x = self.nz(self.state.x.wrapping_add(1)),
+            "INY" => self.state.y = self.nz(self.state.y.wrapping_add(1)),
+            "NOP" => {},
+            "TAX" => self.state.x = self.nz(self.state.a),
+            "TAY" => self.state.y = self.nz(self.state.a),
+            "TSX" => self.state.x = self.nz(self.state.s),
+            "TXA" => self.state.a = self.nz(self.state.x),
+            "TXS" => self.state.s = self.state.x,
+            "TYA" => self.state.a = self.nz(self.state.y),
+            "PHA" => { let a = self.state.a; self.push(a) },
+            "PHP" => { let p = self.state.p | B | U; self.push(p) },
+            "PLA" => { let a = self.pull(); self.state.a = self.nz(a) },
+            "PLP" => self.state.p = self.pull() | B | U,
+            "JMP" => self.state.pc = address,
+            "JSR" => {
+                // the high byte of the target is read after the return
+                // address was pushed, which matters when the two overlap
+                let low = self.fetch() as u16;
+                let pc = self.state.pc;
+                self.push((pc >> 8) as u8);
+                self.push(pc as u8);
+                let high = self.read(pc) as u16;
+                self.state.pc = low | high << 8;
+            },
+            "RTS" => {
+                let low = self.pull() as u16;
+                let high = self.pull() as u16;
+                self.state.pc = (low | high << 8).wrapping_add(1);
+            },
+            "RTI" => {
+                self.state.p = self.pull() | B | U;
+                let low = self.pull() as u16;
+                let high = self.pull() as u16;
+                self.state.pc = low | high << 8;
+            },
+            "BRK" => {
+                let pc = self.state.pc.wrapping_add(1);
+                self.push((pc >> 8) as u8);
+                self.push(pc as u8);
+                let p = self.state.p | B | U;
+                self.push(p);
+                self.set_flag(I, true);
+                self.state.pc = self.read(0xfffe) as u16 | (self.read(0xffff) as u16) << 8;
+            },
+            mnemonic => panic!("no reference implementation for {}", mnemonic)
+        }
+        if page_penalty {
+            self.cycles += 1;
+        }
+        Some(())
+    }
+}
+
+impl Default for Reference {
+    fn default() -> Self {
+        Reference::new()
+    }
+}
+
+/// A case on which the CPU and the reference disagree.
+#[derive(Debug)]
+pub struct Divergence {
+    pub case: u64,
+    pub bytes: [u8; 3],
+    pub initial: State,
+    pub differences: Vec<String>
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "case {}: {:02X} {:02X} {:02X} from {}",
+                 self.case, self.bytes[0], self.bytes[1], self.bytes[2], self.initial)?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+fn cpu_state(cpu: &Cpu) -> State {
+    State {
+        pc: cpu.registers.pc as u16,
+        s: cpu.registers.s as u8,
+        a: cpu.registers.a,
+        x: cpu.registers.x,
+        y: cpu.registers.y,
+        p: cpu.registers.p.to_byte(true)
+    }
+}
+
+/// Runs `cases` random single instructions derived from `seed`, stopping at
+/// the first divergence. Undocumented opcodes are expected to fail.
+pub fn run(seed: u64, cases: u64) -> Result<(), Divergence> {
+    let mut rng = Rng::new(seed);
+    let mut reference = Reference::new();
+    let mut cpu = CpuBuilder::new().build();
+    let mut image = vec![0; 0x10000];
+    for case in 0..cases {
+        // a fresh memory image now and then keeps the cases varied enough
+        if case % 256 == 0 {
+            rng.fill(&mut image);
+        }
+        let initial = State {
+            pc: rng.next_u64() as u16,
+            s: rng.byte(),
+            a: rng.byte(),
+            x: rng.byte(),
+            y: rng.byte(),
+            p: rng.byte() | B | U
+        };
+        let bytes = [rng.byte(), rng.byte(), rng.byte()];
+        let documented = reference.is_documented(bytes[0]);
+        for (i, &byte) in bytes.iter().enumerate() {
+            image[initial.pc.wrapping_add(i as u16) as usize] = byte;
+        }
+
+        reference.memory.copy_from_slice(&image);
+        reference.state = initial;
+        reference.cycles = 0;
+        reference.step();
+
+        cpu.bus.copy_from_slice(&image);
+        cpu.registers = Registers {
+            p: initial.p.into(),
+            pc: initial.pc as usize,
+            a: initial.a,
+            x: initial.x,
+            y: initial.y,
+            s: initial.s as usize
+        };
+        cpu.cycles = 0;
+        cpu.delayed_irq_mask = None;
+        let mut differences = Vec::new();
+        match cpu.step() {
+            Err(e) if documented => differences.push(format!("step failed: {}", e.kind)),
+            Ok(()) if !documented => differences.push("executed an undocumented opcode".to_string()),
+            Err(_) => continue,
+            Ok(()) => {}
+        }
+
+        let state = cpu_state(&cpu);
+        if state != reference.state {
+            differences.push(format!("registers {}, expected {}", state, reference.state));
+        }
+        if cpu.cycles != reference.cycles {
+            differences.push(format!("took {} cycles, expected {}", cpu.cycles, reference.cycles));
+        }
+        if cpu.bus[..] != reference.memory[..] {
+            for address in 0..0x10000 {
+                let (actual, expected) = (cpu.bus[address], reference.memory[address]);
+                if actual != expected {
+                    differences.push(format!("[{:04X}] is {:02X}, expected {:02X}", address, actual, expected));
+                }
+            }
+        }
+        if !differences.is_empty() {
+            return Err(Divergence { case, bytes, initial, differences });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! A GDB remote serial protocol stub, so that gdb builds with 6502 support
+//! (or any other RSP client) can inspect and control a `Cpu` over TCP.
+//!
+//! Registers are numbered A, X, Y, S, P (8 bits each) and PC (16 bits, little
+//! endian); `g` returns them in that order. Supported packets: `?`, `g`, `G`,
+//! `p`, `P`, `m`, `M`, `c`, `s`, `Z0`-`Z4`, `z0`-`z4`, `k`, `D`, `qSupported`,
+//! `QStartNoAckMode` and a handful of thread queries gdb insists on. Sending
+//! `^C` while the target runs interrupts it.
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use super::{Bus, Cpu};
+use super::debugger::{Access, StopReason, Watchpoint};
+
+/// How many cycles the CPU runs between checks for a `^C` from the client.
+const SLICE: u64 = 10_000;
+/// The largest packet we accept and send, as advertised in `qSupported`.
+const PACKET_SIZE: usize = 0x4000;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+pub fn listen<B: Bus, A: ToSocketAddrs>(cpu: &mut Cpu<B>, address: A) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let (stream, _) = listener.accept()?;
+    serve(cpu, stream)
+}
+
+pub fn serve<B: Bus>(cpu: &mut Cpu<B>, stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut session = Session {
+        cpu,
+        stream,
+        pending: VecDeque::new(),
+        no_ack: false
+    };
+    session.run()
+}
+
+struct Session<'a, B: Bus> {
+    cpu: &'a mut Cpu<B>,
+    stream: TcpStream,
+    pending: VecDeque<u8>,
+    no_ack: bool
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn number(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn address_and_length(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let address = number(parts.next()?)?;
+    let length = number(parts.next()?)?;
+    Some((address, length))
+}
+
+enum Outcome {
+    Reply(String),
+    Close
+}
+
+impl<'a, B: Bus> Session<'a, B> {
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buffer = [0; 1024];
+        let read = self.stream.read(&mut buffer)?;
+        if read == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "gdb client disconnected"));
+        }
+        self.pending.extend(&buffer[..read]);
+        Ok(())
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        loop {
+            if let Some(byte) = self.pending.pop_front() {
+                return Ok(byte);
+            }
+            self.fill()?;
+        }
+    }
+
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let result = self.fill();
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {},
+            other => other?
+        }
+        let interrupted = self.pending.contains(&0x03);
+        self.pending.retain(|&b| b != 0x03);
+        Ok(interrupted)
+    }
+
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.next_byte()? {
+                b'$' => break,
+                0x03 => return Ok(Some("?".to_string())),
+                _ => {}
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            match self.next_byte()? {
+                b'#' => break,
+                byte => data.push(byte)
+            }
+        }
+        let checksum = [self.next_byte()?, self.next_byte()?];
+        let expected = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let valid = ::std::str::from_utf8(&checksum).ok()
+            .and_then(|c| u8::from_str_radix(c, 16).ok()) == Some(expected);
+        if !self.no_ack {
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+        }
+        if !valid {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, checksum)?;
+        self.stream.flush()
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e)
+            };
+            match self.handle(&packet)? {
+                Outcome::Reply(reply) => self.send(&reply)?,
+                Outcome::Close => return Ok(())
+            }
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let r = &self.cpu.registers;
+        let pc = r.pc as u16;
+        vec![r.a, r.x, r.y, r.s as u8, r.p.to_byte(true), pc as u8, (pc >> 8) as u8]
+    }
+
+    fn set_register(&mut self, index: usize, bytes: &[u8]) -> bool {
+        let r = &mut self.cpu.registers;
+        match (index, bytes.len()) {
+            (0, 1) => r.a = bytes[0],
+            (1, 1) => r.x = bytes[0],
+            (2, 1) => r.y = bytes[0],
+            (3, 1) => r.s = bytes[0] as usize,
+            (4, 1) => r.p = bytes[0].into(),
+            (5, 2) => r.pc = bytes[0] as usize | ((bytes[1] as usize) << 8),
+            _ => return false
+        }
+        true
+    }
+
+    fn stop_reply(reason: StopReason) -> String {
+        match reason {
+            StopReason::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint { address, access, .. } => {
+                let kind = match access {
+                    Access::Read => "rwatch",
+                    Access::Write => "watch"
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, address)
+            },
+            StopReason::CyclesElapsed | StopReason::StepComplete => format!("S{:02x}", SIGTRAP),
+            StopReason::Halted(_) => format!("S{:02x}", SIGILL)
+        }
+    }
+
+    fn resume(&mut self, arguments: &str, single_step: bool) -> io::Result<String> {
+        if let Some(address) = number(arguments) {
+            self.cpu.registers.pc = address & 0xffff;
+        }
+        if single_step {
+            return Ok(match self.cpu.step() {
+                Ok(()) => format!("S{:02x}", SIGTRAP),
+                Err(_) => format!("S{:02x}", SIGILL)
+            });
+        }
+        loop {
+            match self.cpu.run(SLICE) {
+                Ok(StopReason::CyclesElapsed) => {
+                    if self.poll_interrupt()? {
+                        return Ok(format!("S{:02x}", SIGINT));
+                    }
+                    // the next run does not stop where it starts
+                    let pc = self.cpu.registers.pc as u16;
+                    if self.cpu.debugger().has_breakpoint(pc) {
+                        return Ok(Self::stop_reply(StopReason::Breakpoint(pc)));
+                    }
+                },
+                Ok(reason) => return Ok(Self::stop_reply(reason)),
+                Err(_) => return Ok(format!("S{:02x}", SIGILL))
+            }
+        }
+    }
+
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut parts = arguments.splitn(3, ',');
+        let kind = parts.next().and_then(number);
+        let address = parts.next().and_then(number);
+        let length = parts.next().map_or(Some(1), number);
+        let (kind, address) = match (kind, address) {
+            (Some(kind), Some(address)) if address <= 0xffff => (kind, address as u16),
+            _ => return "E01".to_string()
+        };
+        // the length of a breakpoint is the size of the instruction, which
+        // does not matter here; a watchpoint covers up to the whole memory
+        let end = match length {
+            Some(length) if (1..=0x10000).contains(&length) =>
+                (address as usize + length - 1).min(0xffff) as u16,
+            _ if kind >= 2 => return "E01".to_string(),
+            _ => address
+        };
+        let debugger = self.cpu.debugger();
+        let watchpoint = match kind {
+            0 | 1 => {
+                if insert {
+                    debugger.add_breakpoint(address);
+                }
+                else {
+                    debugger.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            },
+            2 => Watchpoint::write(address, end),
+            3 => Watchpoint::read(address, end),
+            4 => Watchpoint::access(address, end),
+            _ => return String::new()
+        };
+        if insert {
+            debugger.add_watchpoint(watchpoint);
+        }
+        else {
+            debugger.remove_watchpoint(watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<Outcome> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => hex(&self.registers()),
+            "G" => {
+                match unhex(arguments) {
+                    Some(ref bytes) if bytes.len() == 7 => {
+                        self.set_register(0, &bytes[0..1]);
+                        self.set_register(1, &bytes[1..2]);
+                        self.set_register(2, &bytes[2..3]);
+                        self.set_register(3, &bytes[3..4]);
+                        self.set_register(4, &bytes[4..5]);
+                        self.set_register(5, &bytes[5..7]);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "p" => {
+                let registers = self.registers();
+                match number(arguments) {
+                    Some(i) if i < 5 => hex(&registers[i..i + 1]),
+                    Some(5) => hex(&registers[5..7]),
+                    _ => "E01".to_string()
+                }
+            },
+            "P" => {
+                let mut parts = arguments.splitn(2, '=');
+                let index = parts.next().and_then(number);
+                let value = parts.next().and_then(unhex);
+                match (index, value) {
+                    (Some(i), Some(v)) if self.set_register(i, &v) => "OK".to_string(),
+                    _ => "E01".to_string()
+                }
+            },
+            "m" => {
+                // the reply has two hex digits for every byte
+                match address_and_length(arguments) {
+                    Some((address, length)) if address <= 0xffff && length <= PACKET_SIZE / 2 => {
+                        let bytes: Vec<u8> = (0..length)
+                            .map(|i| self.cpu.peek(((address + i) & 0xffff) as u16))
+                            .collect();
+                        hex(&bytes)
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "M" => {
+                let mut parts = arguments.splitn(2, ':');
+                let range = parts.next().and_then(address_and_length);
+                let data = parts.next().and_then(unhex);
+                match (range, data) {
+                    (Some((address, length)), Some(ref data)) if address <= 0xffff && data.len() == length => {
+                        let written = data.iter().enumerate()
+                            .all(|(i, &byte)| self.cpu.poke(((address + i) & 0xffff) as u16, byte));
+                        if written {
+                            "OK".to_string()
+                        }
+                        else {
+                            "E02".to_string()
+                        }
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "c" => self.resume(arguments, false)?,
+            "s" => self.resume(arguments, true)?,
+            "Z" => self.breakpoint(true, arguments),
+            "z" => self.breakpoint(false, arguments),
+            "H" => "OK".to_string(),
+            "k" => return Ok(Outcome::Close),
+            "D" => {
+                self.send("OK")?;
+                return Ok(Outcome::Close);
+            },
+            "q" => {
+                if arguments.starts_with("Supported") {
+                    format!("PacketSize={:x};QStartNoAckMode+;swbreak+;hwbreak+", PACKET_SIZE)
+                }
+                else if arguments == "Attached" {
+                    "1".to_string()
+                }
+                else if arguments == "C" {
+                    "QC1".to_string()
+                }
+                else if arguments == "fThreadInfo" {
+                    "m1".to_string()
+                }
+                else if arguments == "sThreadInfo" {
+                    "l".to_string()
+                }
+                else {
+                    String::new()
+                }
+            },
+            "Q" if arguments == "StartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            },
+            _ => String::new()
+        };
+        Ok(Outcome::Reply(reply))
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AddressingMode {
   Immediate,
   ZeroPage,
@@ -10,11 +11,13 @@ pub enum AddressingMode {
   IndirectIndexed
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum JumpAddressingMode {
     Absolute,
     Indirect
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SingleByteMnemonic {
     ASL, CLC, CLD, CLI, CLV,
     DEX, DEY, INX, INY, LSR,
@@ -23,39 +26,47 @@ pub enum SingleByteMnemonic {
     TXS, TYA
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ReadMnemonic {
     ADC, AND, BIT, CMP, CPX,
     CPY, EOR, LDA, LDX, LDY,
     ORA, SBC
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum StoreMnemonic {
     STA, STX, STY
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RMWMnemonic {
     ASL, DEC, INC, LSR,
     ROL, ROR
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PushMnemonic {
     PHA, PHP
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PullMnemonic {
     PLA, PLP
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BranchMnemonic {
     BCC, BCS, BEQ, BMI, BNE,
     BPL, BVC, BVS
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MiscMnemonic {
     JSR, BRK, RTI, RTS
 }
 
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     SingleByte(SingleByteMnemonic),
     Read(ReadMnemonic, AddressingMode),
@@ -68,7 +79,208 @@ pub enum Instruction {
     Misc(MiscMnemonic)
 }
 
-pub fn decode(opcode: u8) -> Option<Instruction> {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperandMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    IndexedIndirect,
+    IndirectIndexed,
+    Indirect,
+    Relative
+}
+
+impl OperandMode {
+    pub const fn operand_length(self) -> usize {
+        match self {
+            OperandMode::Implied | OperandMode::Accumulator => 0,
+            OperandMode::Absolute | OperandMode::AbsoluteX | OperandMode::AbsoluteY
+                | OperandMode::Indirect => 2,
+            _ => 1
+        }
+    }
+
+    pub const fn from_addressing_mode(am: AddressingMode) -> Self {
+        match am {
+            AddressingMode::Immediate => OperandMode::Immediate,
+            AddressingMode::ZeroPage => OperandMode::ZeroPage,
+            AddressingMode::ZeroPageX => OperandMode::ZeroPageX,
+            AddressingMode::ZeroPageY => OperandMode::ZeroPageY,
+            AddressingMode::Absolute => OperandMode::Absolute,
+            AddressingMode::AbsoluteX => OperandMode::AbsoluteX,
+            AddressingMode::AbsoluteY => OperandMode::AbsoluteY,
+            AddressingMode::IndexedIndirect => OperandMode::IndexedIndirect,
+            AddressingMode::IndirectIndexed => OperandMode::IndirectIndexed
+        }
+    }
+}
+
+impl From<AddressingMode> for OperandMode {
+    fn from(am: AddressingMode) -> Self {
+        OperandMode::from_addressing_mode(am)
+    }
+}
+
+impl SingleByteMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            SingleByteMnemonic::ASL => "ASL",
+            SingleByteMnemonic::CLC => "CLC",
+            SingleByteMnemonic::CLD => "CLD",
+            SingleByteMnemonic::CLI => "CLI",
+            SingleByteMnemonic::CLV => "CLV",
+            SingleByteMnemonic::DEX => "DEX",
+            SingleByteMnemonic::DEY => "DEY",
+            SingleByteMnemonic::INX => "INX",
+            SingleByteMnemonic::INY => "INY",
+            SingleByteMnemonic::LSR => "LSR",
+            SingleByteMnemonic::NOP => "NOP",
+            SingleByteMnemonic::ROL => "ROL",
+            SingleByteMnemonic::ROR => "ROR",
+            SingleByteMnemonic::SEC => "SEC",
+            SingleByteMnemonic::SED => "SED",
+            SingleByteMnemonic::SEI => "SEI",
+            SingleByteMnemonic::TAX => "TAX",
+            SingleByteMnemonic::TAY => "TAY",
+            SingleByteMnemonic::TSX => "TSX",
+            SingleByteMnemonic::TXA => "TXA",
+            SingleByteMnemonic::TXS => "TXS",
+            SingleByteMnemonic::TYA => "TYA"
+        }
+    }
+}
+
+impl ReadMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            ReadMnemonic::ADC => "ADC",
+            ReadMnemonic::AND => "AND",
+            ReadMnemonic::BIT => "BIT",
+            ReadMnemonic::CMP => "CMP",
+            ReadMnemonic::CPX => "CPX",
+            ReadMnemonic::CPY => "CPY",
+            ReadMnemonic::EOR => "EOR",
+            ReadMnemonic::LDA => "LDA",
+            ReadMnemonic::LDX => "LDX",
+            ReadMnemonic::LDY => "LDY",
+            ReadMnemonic::ORA => "ORA",
+            ReadMnemonic::SBC => "SBC"
+        }
+    }
+}
+
+impl StoreMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            StoreMnemonic::STA => "STA",
+            StoreMnemonic::STX => "STX",
+            StoreMnemonic::STY => "STY"
+        }
+    }
+}
+
+impl RMWMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            RMWMnemonic::ASL => "ASL",
+            RMWMnemonic::DEC => "DEC",
+            RMWMnemonic::INC => "INC",
+            RMWMnemonic::LSR => "LSR",
+            RMWMnemonic::ROL => "ROL",
+            RMWMnemonic::ROR => "ROR"
+        }
+    }
+}
+
+impl PushMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            PushMnemonic::PHA => "PHA",
+            PushMnemonic::PHP => "PHP"
+        }
+    }
+}
+
+impl PullMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            PullMnemonic::PLA => "PLA",
+            PullMnemonic::PLP => "PLP"
+        }
+    }
+}
+
+impl BranchMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            BranchMnemonic::BCC => "BCC",
+            BranchMnemonic::BCS => "BCS",
+            BranchMnemonic::BEQ => "BEQ",
+            BranchMnemonic::BMI => "BMI",
+            BranchMnemonic::BNE => "BNE",
+            BranchMnemonic::BPL => "BPL",
+            BranchMnemonic::BVC => "BVC",
+            BranchMnemonic::BVS => "BVS"
+        }
+    }
+}
+
+impl MiscMnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            MiscMnemonic::JSR => "JSR",
+            MiscMnemonic::BRK => "BRK",
+            MiscMnemonic::RTI => "RTI",
+            MiscMnemonic::RTS => "RTS"
+        }
+    }
+}
+
+impl Instruction {
+    pub const fn mnemonic(self) -> &'static str {
+        match self {
+            Instruction::SingleByte(m) => m.name(),
+            Instruction::Read(m, _) => m.name(),
+            Instruction::Store(m, _) => m.name(),
+            Instruction::ReadModifyWrite(m, _) => m.name(),
+            Instruction::Push(m) => m.name(),
+            Instruction::Pull(m) => m.name(),
+            Instruction::Branch(m) => m.name(),
+            Instruction::Jump(_) => "JMP",
+            Instruction::Misc(m) => m.name()
+        }
+    }
+
+    pub const fn operand_mode(self) -> OperandMode {
+        match self {
+            Instruction::SingleByte(SingleByteMnemonic::ASL)
+                | Instruction::SingleByte(SingleByteMnemonic::LSR)
+                | Instruction::SingleByte(SingleByteMnemonic::ROL)
+                | Instruction::SingleByte(SingleByteMnemonic::ROR) => OperandMode::Accumulator,
+            Instruction::SingleByte(_) | Instruction::Push(_) | Instruction::Pull(_) => OperandMode::Implied,
+            Instruction::Read(_, am)
+                | Instruction::Store(_, am)
+                | Instruction::ReadModifyWrite(_, am) => OperandMode::from_addressing_mode(am),
+            Instruction::Branch(_) => OperandMode::Relative,
+            Instruction::Jump(JumpAddressingMode::Absolute) => OperandMode::Absolute,
+            Instruction::Jump(JumpAddressingMode::Indirect) => OperandMode::Indirect,
+            Instruction::Misc(MiscMnemonic::JSR) => OperandMode::Absolute,
+            Instruction::Misc(_) => OperandMode::Implied
+        }
+    }
+
+    pub const fn length(self) -> usize {
+        1 + self.operand_mode().operand_length()
+    }
+}
+
+pub const fn decode(opcode: u8) -> Option<Instruction> {
     match opcode {
         0x69 => Some(Instruction::Read(ReadMnemonic::ADC, AddressingMode::Immediate)),
         0x65 => Some(Instruction::Read(ReadMnemonic::ADC, AddressingMode::ZeroPage)),
@@ -210,7 +422,7 @@ pub fn decode(opcode: u8) -> Option<Instruction> {
         0x81 => Some(Instruction::Store(StoreMnemonic::STA, AddressingMode::IndexedIndirect)),
         0x91 => Some(Instruction::Store(StoreMnemonic::STA, AddressingMode::IndirectIndexed)),
         0x86 => Some(Instruction::Store(StoreMnemonic::STX, AddressingMode::ZeroPage)),
-        0x97 => Some(Instruction::Store(StoreMnemonic::STX, AddressingMode::ZeroPageY)),
+        0x96 => Some(Instruction::Store(StoreMnemonic::STX, AddressingMode::ZeroPageY)),
         0x8e => Some(Instruction::Store(StoreMnemonic::STX, AddressingMode::Absolute)),
         0x84 => Some(Instruction::Store(StoreMnemonic::STY, AddressingMode::ZeroPage)),
         0x94 => Some(Instruction::Store(StoreMnemonic::STY, AddressingMode::ZeroPageX)),
@@ -0,0 +1,156 @@
+//! Static per-opcode metadata, derived from `decode` at compile time so that
+//! the profiler, the disassembler and timing tests share one authoritative
+//! source for instruction lengths, cycle counts and flag usage.
+
+use super::instruction::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags(pub u8);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    pub const CARRY: Flags = Flags(0x01);
+    pub const ZERO: Flags = Flags(0x02);
+    pub const IRQ_DISABLED: Flags = Flags(0x04);
+    pub const DECIMAL_MODE: Flags = Flags(0x08);
+    pub const OVERFLOW: Flags = Flags(0x40);
+    pub const NEGATIVE: Flags = Flags(0x80);
+    pub const ALL: Flags = Flags(0xcf);
+
+    pub const fn union(self, other: Flags) -> Flags {
+        Flags(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub instruction: Instruction,
+    pub length: u8,
+    pub cycles: u8,
+    pub page_cross_penalty: bool,
+    pub branch_penalty: bool,
+    pub reads: Flags,
+    pub writes: Flags
+}
+
+pub static OPCODES: [Option<OpcodeInfo>; 256] = build_table();
+
+pub fn opcode_info(opcode: u8) -> Option<&'static OpcodeInfo> {
+    OPCODES[opcode as usize].as_ref()
+}
+
+const NZ: Flags = Flags(Flags::NEGATIVE.0 | Flags::ZERO.0);
+const NZC: Flags = Flags(NZ.0 | Flags::CARRY.0);
+
+const fn build_table() -> [Option<OpcodeInfo>; 256] {
+    let mut table = [None; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        table[opcode] = match decode(opcode as u8) {
+            Some(instruction) => Some(describe(instruction)),
+            None => None
+        };
+        opcode += 1;
+    }
+    table
+}
+
+const fn describe(instruction: Instruction) -> OpcodeInfo {
+    let (reads, writes) = flags(instruction);
+    OpcodeInfo {
+        instruction,
+        length: instruction.length() as u8,
+        cycles: base_cycles(instruction),
+        page_cross_penalty: matches!(instruction,
+            Instruction::Read(_, AddressingMode::AbsoluteX)
+                | Instruction::Read(_, AddressingMode::AbsoluteY)
+                | Instruction::Read(_, AddressingMode::IndirectIndexed)
+                | Instruction::Branch(_)),
+        branch_penalty: matches!(instruction, Instruction::Branch(_)),
+        reads,
+        writes
+    }
+}
+
+const fn addressing_cycles(am: AddressingMode) -> u8 {
+    match am {
+        AddressingMode::Immediate => 2,
+        AddressingMode::ZeroPage => 3,
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => 4,
+        AddressingMode::Absolute => 4,
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 4,
+        AddressingMode::IndexedIndirect => 6,
+        AddressingMode::IndirectIndexed => 5
+    }
+}
+
+const fn base_cycles(instruction: Instruction) -> u8 {
+    match instruction {
+        Instruction::SingleByte(_) => 2,
+        Instruction::Read(_, am) => addressing_cycles(am),
+        Instruction::Store(_, AddressingMode::AbsoluteX)
+            | Instruction::Store(_, AddressingMode::AbsoluteY) => 5,
+        Instruction::Store(_, AddressingMode::IndirectIndexed) => 6,
+        Instruction::Store(_, am) => addressing_cycles(am),
+        Instruction::ReadModifyWrite(_, AddressingMode::ZeroPage) => 5,
+        Instruction::ReadModifyWrite(_, AddressingMode::AbsoluteX) => 7,
+        Instruction::ReadModifyWrite(_, _) => 6,
+        Instruction::Push(_) => 3,
+        Instruction::Pull(_) => 4,
+        Instruction::Branch(_) => 2,
+        Instruction::Jump(JumpAddressingMode::Absolute) => 3,
+        Instruction::Jump(JumpAddressingMode::Indirect) => 5,
+        Instruction::Misc(MiscMnemonic::BRK) => 7,
+        Instruction::Misc(_) => 6
+    }
+}
+
+const fn flags(instruction: Instruction) -> (Flags, Flags) {
+    match instruction {
+        Instruction::SingleByte(m) => match m {
+            SingleByteMnemonic::ASL | SingleByteMnemonic::LSR => (Flags::NONE, NZC),
+            SingleByteMnemonic::ROL | SingleByteMnemonic::ROR => (Flags::CARRY, NZC),
+            SingleByteMnemonic::CLC | SingleByteMnemonic::SEC => (Flags::NONE, Flags::CARRY),
+            SingleByteMnemonic::CLD | SingleByteMnemonic::SED => (Flags::NONE, Flags::DECIMAL_MODE),
+            SingleByteMnemonic::CLI | SingleByteMnemonic::SEI => (Flags::NONE, Flags::IRQ_DISABLED),
+            SingleByteMnemonic::CLV => (Flags::NONE, Flags::OVERFLOW),
+            SingleByteMnemonic::NOP | SingleByteMnemonic::TXS => (Flags::NONE, Flags::NONE),
+            _ => (Flags::NONE, NZ)
+        },
+        Instruction::Read(m, _) => match m {
+            ReadMnemonic::ADC | ReadMnemonic::SBC =>
+                (Flags::CARRY.union(Flags::DECIMAL_MODE), NZC.union(Flags::OVERFLOW)),
+            ReadMnemonic::BIT => (Flags::NONE, NZ.union(Flags::OVERFLOW)),
+            ReadMnemonic::CMP | ReadMnemonic::CPX | ReadMnemonic::CPY => (Flags::NONE, NZC),
+            _ => (Flags::NONE, NZ)
+        },
+        Instruction::Store(_, _) => (Flags::NONE, Flags::NONE),
+        Instruction::ReadModifyWrite(m, _) => match m {
+            RMWMnemonic::ASL | RMWMnemonic::LSR => (Flags::NONE, NZC),
+            RMWMnemonic::ROL | RMWMnemonic::ROR => (Flags::CARRY, NZC),
+            RMWMnemonic::DEC | RMWMnemonic::INC => (Flags::NONE, NZ)
+        },
+        Instruction::Push(PushMnemonic::PHA) => (Flags::NONE, Flags::NONE),
+        Instruction::Push(PushMnemonic::PHP) => (Flags::ALL, Flags::NONE),
+        Instruction::Pull(PullMnemonic::PLA) => (Flags::NONE, NZ),
+        Instruction::Pull(PullMnemonic::PLP) => (Flags::NONE, Flags::ALL),
+        Instruction::Branch(m) => (match m {
+            BranchMnemonic::BCC | BranchMnemonic::BCS => Flags::CARRY,
+            BranchMnemonic::BEQ | BranchMnemonic::BNE => Flags::ZERO,
+            BranchMnemonic::BMI | BranchMnemonic::BPL => Flags::NEGATIVE,
+            BranchMnemonic::BVC | BranchMnemonic::BVS => Flags::OVERFLOW
+        }, Flags::NONE),
+        Instruction::Jump(_) => (Flags::NONE, Flags::NONE),
+        Instruction::Misc(MiscMnemonic::BRK) => (Flags::ALL, Flags::IRQ_DISABLED),
+        Instruction::Misc(MiscMnemonic::RTI) => (Flags::NONE, Flags::ALL),
+        Instruction::Misc(_) => (Flags::NONE, Flags::NONE)
+    }
+}
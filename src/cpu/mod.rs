@@ -0,0 +1,1551 @@
+pub mod instruction;
+pub mod bus;
+pub mod builder;
+pub mod metadata;
+pub mod dispatch;
+pub mod debugger;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod disassembler;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tracediff;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod monitor;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod cdl;
+#[cfg(feature = "std")]
+pub mod profiler;
+#[cfg(feature = "std")]
+pub mod callstack;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(all(test, feature = "std"))]
+mod test;
+use self::instruction::*;
+pub use self::bus::{Bus, DefaultBus, Memory};
+#[cfg(feature = "std")]
+pub use self::bus::Ram;
+pub use self::builder::CpuBuilder;
+use self::dispatch::{Handler, Variant};
+use self::debugger::{Access, StopReason};
+pub use self::error::{Error, ErrorKind};
+#[cfg(feature = "std")]
+use self::trace::Tracer;
+#[cfg(feature = "std")]
+use self::debugger::Debugger;
+#[cfg(feature = "std")]
+use self::cdl::{AccessKind, CodeDataLogger};
+#[cfg(feature = "std")]
+use self::profiler::Profiler;
+#[cfg(feature = "std")]
+use self::callstack::{CallStack, FrameKind};
+#[cfg(feature = "std")]
+use self::disassembler::disassemble;
+#[cfg(feature = "std")]
+use self::error::HISTORY_LENGTH;
+use core::fmt;
+
+const NMI_VECTOR: usize = 0xfffa;
+const RESET_VECTOR: usize = 0xfffc;
+const IRQ_VECTOR: usize = 0xfffe;
+
+// Cycles spent computing the effective address before the first data access;
+// the totals the metadata table records live in metadata::addressing_cycles
+const fn address_cycles_before_access(am: AddressingMode) -> u8 {
+    match am {
+        AddressingMode::Immediate => 0,
+        AddressingMode::ZeroPage => 1,
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY | AddressingMode::Absolute => 2,
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 3,
+        AddressingMode::IndexedIndirect | AddressingMode::IndirectIndexed => 4
+    }
+}
+
+fn same_sign(lhs: u8, rhs: u8) -> bool {
+    lhs & 0x80 == rhs & 0x80
+}
+
+trait Address where Self: Sized {
+    fn low_byte(self) -> u8;
+    fn high_byte(self) -> u8;
+    fn combine_low_high(low: u8, high: u8) -> Self;
+    fn add_offset(self, offset: u8) -> Self;
+    fn on_different_page(self, rhs: Self) -> bool {
+        self.high_byte() != rhs.high_byte()
+    }
+}
+
+impl Address for usize {
+    fn low_byte(self) -> u8 {
+        (self & 0xff) as u8
+    }
+
+    fn high_byte(self) -> u8 {
+        ((self & 0xff00) >> 8) as u8
+    }
+
+    fn combine_low_high(low: u8, high: u8) -> Self {
+        low as Self | ((high as Self) << 8)
+    }
+
+    fn add_offset(self, offset: u8) -> usize {
+        (self as u16).wrapping_add(offset as i8 as u16) as usize
+    }
+}
+
+/// The processor status flags. B and bit 5 have no storage in the register;
+/// they only exist in the copy of P pushed on the stack, see `to_byte`.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StatusRegister {
+    carry: bool,
+    zero: bool,
+    irq_disabled: bool,
+    decimal_mode: bool,
+    overflow: bool,
+    negative: bool
+}
+
+impl StatusRegister {
+    /// Unpacks P as pulled by PLP and RTI, ignoring B and bit 5.
+    pub fn from_byte(value: u8) -> Self {
+        Self {
+            carry: value & 0x01 != 0,
+            zero: value & 0x02 != 0,
+            irq_disabled: value & 0x04 != 0,
+            decimal_mode: value & 0x08 != 0,
+            overflow: value & 0x40 != 0,
+            negative: value & 0x80 != 0
+        }
+    }
+
+    /// Packs P as it is pushed: bit 5 always reads as set, B is set by PHP
+    /// and BRK but clear when an interrupt pushes P.
+    pub fn to_byte(self, b: bool) -> u8 {
+        0x20 | from_bool(b, 0x10)
+             | from_bool(self.carry, 0x01)
+             | from_bool(self.zero, 0x02)
+             | from_bool(self.irq_disabled, 0x04)
+             | from_bool(self.decimal_mode, 0x08)
+             | from_bool(self.overflow, 0x40)
+             | from_bool(self.negative, 0x80)
+    }
+
+    pub fn carry(&self) -> bool {
+        self.carry
+    }
+
+    pub fn set_carry(&mut self, value: bool) {
+        self.carry = value;
+    }
+
+    pub fn zero(&self) -> bool {
+        self.zero
+    }
+
+    pub fn set_zero(&mut self, value: bool) {
+        self.zero = value;
+    }
+
+    pub fn irq_disabled(&self) -> bool {
+        self.irq_disabled
+    }
+
+    pub fn set_irq_disabled(&mut self, value: bool) {
+        self.irq_disabled = value;
+    }
+
+    pub fn decimal_mode(&self) -> bool {
+        self.decimal_mode
+    }
+
+    pub fn set_decimal_mode(&mut self, value: bool) {
+        self.decimal_mode = value;
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.overflow
+    }
+
+    pub fn set_overflow(&mut self, value: bool) {
+        self.overflow = value;
+    }
+
+    pub fn negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn set_negative(&mut self, value: bool) {
+        self.negative = value;
+    }
+
+    fn carry_from_u8(&mut self, val: u8) {
+        self.carry = val != 0;
+    }
+
+    fn carry_into_u8(&self) -> u8 {
+        if self.carry {
+            1
+        }
+        else {
+            0
+        }
+    }
+}
+
+impl From<u8> for StatusRegister {
+    fn from(val: u8) -> Self {
+        StatusRegister::from_byte(val)
+    }
+}
+
+/// Renders the flags as `NV-BDIZC`, upper case when set and lower case when
+/// clear. B is always shown clear since the register cannot hold it.
+impl fmt::Display for StatusRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool, name: char| if set { name } else { name.to_ascii_lowercase() };
+        write!(f, "{}{}-b{}{}{}{}", flag(self.negative, 'N'), flag(self.overflow, 'V'),
+               flag(self.decimal_mode, 'D'), flag(self.irq_disabled, 'I'),
+               flag(self.zero, 'Z'), flag(self.carry, 'C'))
+    }
+}
+
+fn from_bool(b: bool, val: u8) -> u8 {
+    if b {
+        val 
+    }
+    else {
+        0
+    }
+}
+
+/// Emulates an illegal opcode for `IllegalOpcodePolicy::Callback`.
+#[cfg(feature = "std")]
+pub type IllegalOpcodeHandler<B> = Box<dyn FnMut(&mut Cpu<B>, u8) -> Result<(), ErrorKind>>;
+
+/// What the CPU does with an opcode that `decode` does not know.
+#[derive(Default)]
+pub enum IllegalOpcodePolicy<B: Bus = DefaultBus> {
+    /// Stop with an error, the default.
+    #[default]
+    Fail,
+    /// Skip the opcode as a one byte, two cycle NOP.
+    Nop,
+    /// Lock up like the NMOS KIL opcodes: the processor stops fetching and
+    /// only `reset` starts it again.
+    Halt,
+    /// Let the callback emulate the opcode. It is called with PC just past
+    /// the opcode; an error it returns is raised as if the CPU had failed.
+    #[cfg(feature = "std")]
+    Callback(IllegalOpcodeHandler<B>),
+    #[doc(hidden)]
+    #[cfg(not(feature = "std"))]
+    _Bus(core::marker::PhantomData<B>)
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    p: StatusRegister,
+    pc: usize,
+    a: u8,
+    x: u8,
+    y: u8,
+    s: usize
+}
+
+impl Registers {
+    pub fn pc(&self) -> u16 {
+        self.pc as u16
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value as usize;
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn set_a(&mut self, value: u8) {
+        self.a = value;
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, value: u8) {
+        self.x = value;
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, value: u8) {
+        self.y = value;
+    }
+
+    /// The stack pointer, the low byte of the stack address in page 1.
+    pub fn s(&self) -> u8 {
+        self.s as u8
+    }
+
+    pub fn set_s(&mut self, value: u8) {
+        self.s = value as usize;
+    }
+
+    pub fn p(&self) -> StatusRegister {
+        self.p
+    }
+
+    pub fn set_p(&mut self, value: StatusRegister) {
+        self.p = value;
+    }
+
+    pub fn p_mut(&mut self) -> &mut StatusRegister {
+        &mut self.p
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}",
+               self.pc, self.a, self.x, self.y, self.s, self.p)
+    }
+}
+
+pub struct Cpu<B: Bus = DefaultBus> {
+    bus: B,
+    cycles: u64,
+    registers: Registers,
+    variant: Variant,
+    handlers: &'static [Handler<B>; 256],
+    #[cfg(feature = "std")]
+    tracer: Option<Tracer>,
+    #[cfg(feature = "std")]
+    debugger: Debugger,
+    #[cfg(feature = "std")]
+    logger: Option<CodeDataLogger>,
+    #[cfg(feature = "std")]
+    profiler: Option<Profiler>,
+    #[cfg(feature = "std")]
+    call_stack: Option<CallStack>,
+    instruction_pc: usize,
+    /// The cycle of the current instruction the next tick runs, 0 at an
+    /// instruction boundary where it fetches an opcode or starts servicing
+    /// an interrupt.
+    t_state: u8,
+    handler: Handler<B>,
+    opcode: u8,
+    servicing_interrupt: bool,
+    // what the current instruction fetched in earlier cycles
+    address: usize,
+    pointer: u8,
+    data: u8,
+    initial_irq_disabled: bool,
+    #[cfg(feature = "std")]
+    start_cycles: u64,
+    #[cfg(feature = "std")]
+    history: [u16; HISTORY_LENGTH],
+    #[cfg(feature = "std")]
+    instructions: u64,
+    illegal_opcode_policy: IllegalOpcodePolicy<B>,
+    halted: bool,
+    fault: Option<ErrorKind>,
+    data_bus: u8,
+    irq: bool,
+    nmi: bool,
+    nmi_pending: bool,
+    delayed_irq_mask: Option<bool>
+}
+
+#[cfg(feature = "std")]
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::with_bus(Ram::new())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Cpu {
+    pub fn new(r: Registers) -> Self {
+        Cpu { 
+            registers: r,
+            .. Default::default()
+        }
+    }
+
+    pub fn fill_memory(&mut self, from: usize, with: &[u8]) {
+        let end = from + with.len();
+        assert!(end <= 65536, "Filling memory would exceed 64K mark");
+        self.bus[from..end].clone_from_slice(with);
+    }
+
+}
+
+impl<B: Bus> Cpu<B> {
+    pub fn with_bus(bus: B) -> Self {
+        Cpu {
+            bus,
+            cycles: 0,
+            registers: Default::default(),
+            variant: Variant::default(),
+            handlers: Variant::default().handlers(),
+            #[cfg(feature = "std")]
+            tracer: None,
+            #[cfg(feature = "std")]
+            debugger: Debugger::default(),
+            #[cfg(feature = "std")]
+            logger: None,
+            #[cfg(feature = "std")]
+            profiler: None,
+            #[cfg(feature = "std")]
+            call_stack: None,
+            instruction_pc: 0,
+            t_state: 0,
+            handler: Self::service_interrupt,
+            opcode: 0,
+            servicing_interrupt: false,
+            address: 0,
+            pointer: 0,
+            data: 0,
+            initial_irq_disabled: false,
+            #[cfg(feature = "std")]
+            start_cycles: 0,
+            #[cfg(feature = "std")]
+            history: [0; HISTORY_LENGTH],
+            #[cfg(feature = "std")]
+            instructions: 0,
+            illegal_opcode_policy: IllegalOpcodePolicy::default(),
+            halted: false,
+            fault: None,
+            data_bus: 0,
+            irq: false,
+            nmi: false,
+            nmi_pending: false,
+            delayed_irq_mask: None
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Reads memory the way a debugger should: without using a cycle,
+    /// without side effects on devices and without disturbing the open
+    /// bus. Unmapped addresses read as the open bus value.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address).unwrap_or(self.data_bus)
+    }
+
+    /// Changes memory without using a cycle, side effects or touching the
+    /// open bus. Returns false if the bus does not allow it at `address`.
+    pub fn poke(&mut self, address: u16, value: u8) -> bool {
+        self.bus.poke(address, value)
+    }
+
+    /// The value last driven on the data bus, which is what the CPU reads
+    /// from addresses nothing responds to.
+    pub fn open_bus(&self) -> u8 {
+        self.data_bus
+    }
+
+    fn s_into_byte(&self) -> u8 {
+        (self.registers.s & 0xff) as u8
+    }
+
+    fn s_from_byte(&mut self, val: u8) {
+        self.registers.s = val as usize;
+    }
+
+    fn set_zn(&mut self, val: u8) {
+        self.registers.p.zero = val == 0;
+        self.registers.p.negative = val & 0x80 == 0x80;
+    }
+
+    fn get_x(&self) -> u8 {
+        self.registers.x
+    }
+
+    fn get_y(&self) -> u8 {
+        self.registers.y
+    }
+
+
+    fn set_a(&mut self, val: u8) {
+        self.registers.a = val;
+        self.set_zn(val)
+    }
+
+    fn set_x(&mut self, val: u8) {
+        self.registers.x = val;
+        self.set_zn(val)
+    }
+
+    fn set_y(&mut self, val: u8) {
+        self.registers.y = val;
+        self.set_zn(val)
+    }
+
+    /// The master clock timestamp: every bus access adds the time the bus
+    /// says it takes, one by default, so that a CPU on its own counts its
+    /// cycles. See `scheduler` for running it alongside other chips.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.registers.pc as u16
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.registers.pc = pc as usize;
+    }
+
+    /// Drives the IRQ line. The interrupt is taken before the next
+    /// instruction for as long as the line is active and I is clear.
+    pub fn set_irq(&mut self, active: bool) {
+        self.irq = active;
+    }
+
+    /// Drives the NMI line. NMI is edge triggered: only a change from
+    /// inactive to active causes an interrupt.
+    pub fn set_nmi(&mut self, active: bool) {
+        if active && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = active;
+    }
+
+    pub fn set_illegal_opcode_policy(&mut self, policy: IllegalOpcodePolicy<B>) -> IllegalOpcodePolicy<B> {
+        ::core::mem::replace(&mut self.illegal_opcode_policy, policy)
+    }
+
+    pub fn illegal_opcode_policy(&self) -> &IllegalOpcodePolicy<B> {
+        &self.illegal_opcode_policy
+    }
+
+    /// Whether the processor has locked up, see `IllegalOpcodePolicy::Halt`.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Runs the reset sequence: three stack reads that move S down as if
+    /// for an interrupt, then PC is loaded from the reset vector with I set.
+    /// A halted processor starts running again, and an instruction that was
+    /// partly executed by `tick` is abandoned.
+    pub fn reset(&mut self) {
+        self.t_state = 0;
+        self.halted = false;
+        self.nmi_pending = false;
+        self.delayed_irq_mask = None;
+        #[cfg(feature = "std")]
+        if let Some(ref mut call_stack) = self.call_stack {
+            call_stack.clear();
+        }
+        let pc = self.registers.pc;
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+        for _ in 0..3 {
+            let s = self.stack_address();
+            self.dummy_read(s);
+            self.registers.s = self.registers.s.wrapping_sub(1) & 0xff;
+        }
+        self.registers.p.irq_disabled = true;
+        let adl = self.read_byte(RESET_VECTOR);
+        let adh = self.read_byte(RESET_VECTOR + 1);
+        self.registers.pc = Address::combine_low_high(adl, adh);
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        ::core::mem::replace(&mut self.tracer, tracer)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_logger(&mut self, logger: Option<CodeDataLogger>) -> Option<CodeDataLogger> {
+        ::core::mem::replace(&mut self.logger, logger)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn logger(&self) -> Option<&CodeDataLogger> {
+        self.logger.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+        ::core::mem::replace(&mut self.profiler, profiler)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_call_stack(&mut self, call_stack: Option<CallStack>) -> Option<CallStack> {
+        ::core::mem::replace(&mut self.call_stack, call_stack)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.call_stack.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_stack_mut(&mut self) -> Option<&mut CallStack> {
+        self.call_stack.as_mut()
+    }
+
+    /// Runs until the current instruction or interrupt sequence is complete.
+    /// At an instruction boundary, that executes one whole instruction.
+    pub fn step(&mut self) -> Result<(), Error> {
+        self.run_cycles(true)
+    }
+
+    /// Runs a single cycle, which is exactly one bus access, so that other
+    /// chips can run in lockstep with the CPU and see what an instruction
+    /// does in the middle of it. An instruction takes as many ticks as it
+    /// takes cycles, and the tick that completes it raises its errors. A
+    /// halted processor only lets the cycle pass.
+    pub fn tick(&mut self) -> Result<(), Error> {
+        self.run_cycles(false)
+    }
+
+    #[inline]
+    fn run_cycles(&mut self, to_completion: bool) -> Result<(), Error> {
+        if self.halted {
+            let pc = self.registers.pc;
+            self.full_cycle(pc);
+            return Ok(());
+        }
+        if self.t_state == 0 {
+            let result = self.begin();
+            self.t_state = 1;
+            match result {
+                Ok(false) if to_completion => {},
+                Ok(false) => return Ok(()),
+                result => return self.complete(result.map(|_| ()))
+            }
+        }
+        let result = (self.handler)(self, to_completion);
+        self.t_state += 1;
+        match result {
+            Ok(false) => Ok(()),
+            result => self.complete(result.map(|_| ()))
+        }
+    }
+
+    /// Whether the CPU is between instructions, where the next tick fetches
+    /// an opcode or starts servicing an interrupt.
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.t_state == 0
+    }
+
+    // The first cycle: poll the interrupt lines, then either start servicing
+    // an interrupt or fetch the next opcode
+    fn begin(&mut self) -> Result<bool, ErrorKind> {
+        self.instruction_pc = self.registers.pc;
+        #[cfg(feature = "std")]
+        {
+            self.start_cycles = self.cycles;
+        }
+        let irq_masked = self.delayed_irq_mask.take().unwrap_or(self.registers.p.irq_disabled);
+        self.servicing_interrupt = self.nmi_pending || (self.irq && !irq_masked);
+        if self.servicing_interrupt {
+            self.handler = Self::service_interrupt;
+            self.bogus_read_pc();
+            return Ok(false);
+        }
+        #[cfg(feature = "std")]
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self);
+            self.tracer = Some(tracer);
+        }
+        self.initial_irq_disabled = self.registers.p.irq_disabled;
+        #[cfg(feature = "std")]
+        {
+            self.history[self.instructions as usize % HISTORY_LENGTH] = self.instruction_pc as u16;
+            self.instructions += 1;
+        }
+        self.opcode = self.fetch_opcode();
+        if !self.variant.is_documented(self.opcode) {
+            return self.illegal_opcode(self.opcode);
+        }
+        self.handler = self.handlers[self.opcode as usize];
+        Ok(false)
+    }
+
+    fn complete(&mut self, result: Result<(), ErrorKind>) -> Result<(), Error> {
+        self.t_state = 0;
+        let result = match self.fault.take() {
+            Some(kind) => Err(self.error(kind)),
+            None => result.map_err(|kind| self.error(kind))
+        };
+        if self.servicing_interrupt {
+            #[cfg(feature = "std")]
+            if let Some(ref mut profiler) = self.profiler {
+                profiler.interrupt(self.instruction_pc as u16, self.registers.pc as u16,
+                                   self.cycles - self.start_cycles, self.registers.s as u8);
+            }
+            return result;
+        }
+        // CLI, SEI and PLP change I after the interrupt lines were polled,
+        // so their effect is delayed by one instruction
+        if let 0x58 | 0x78 | 0x28 = self.opcode {
+            self.delayed_irq_mask = Some(self.initial_irq_disabled);
+        }
+        #[cfg(feature = "std")]
+        if let Some(ref mut profiler) = self.profiler {
+            let pc = self.instruction_pc as u16;
+            profiler.record(pc, self.opcode, self.cycles - self.start_cycles, self.registers.pc as u16,
+                            self.registers.s as u8);
+        }
+        result
+    }
+
+    fn do_asl(&mut self, val: u8) -> u8 {
+        self.registers.p.carry_from_u8(val & 0x80);
+        val << 1
+    }
+
+    fn do_lsr(&mut self, val: u8) -> u8 {
+        self.registers.p.carry_from_u8(val & 0x01);
+        val >> 1
+    }
+
+    fn do_rol(&mut self, val: u8) -> u8 {
+        let old_c = self.registers.p.carry_into_u8();
+        self.registers.p.carry_from_u8(val & 0x80);
+        (val << 1) | old_c
+    }
+
+    fn do_ror(&mut self, val: u8) -> u8 {
+        let old_c = self.registers.p.carry_into_u8();
+        self.registers.p.carry_from_u8(val & 0x01);
+        (val >> 1) | (old_c << 7)
+    }
+
+    fn mod_x<F>(&mut self, f: F)
+        where F: Fn(&mut Self, u8) -> u8
+    {
+        let x = self.registers.x;
+        let res = f(self, x);
+        self.set_x(res);
+    }
+
+    fn mod_y<F>(&mut self, f: F)
+        where F: Fn(&mut Self, u8) -> u8
+    {
+        let y = self.registers.y;
+        let res = f(self, y);
+        self.set_y(res);
+    }
+
+    fn mod_a<F>(&mut self, mut f: F)
+        where F: FnMut(&mut Self, u8) -> u8
+    {
+        let a = self.registers.a;
+        let res = f(self, a);
+        self.set_a(res);
+    }
+
+    // The executors of the cycles of each kind of instruction are inlined
+    // into the handler of every opcode, where mnemonic and addressing mode
+    // are constants and running the instruction to completion unrolls.
+    //
+    // Runs the current cycle of resolving the effective address of `am` and
+    // returns None, or returns the address once the cycle is free for the
+    // access itself. Reads skip the cycle that fixes the high byte of an
+    // indexed address when no page is crossed, writes always spend it.
+    #[inline(always)]
+    fn address_cycle(&mut self, am: AddressingMode, read: bool) -> Option<usize> {
+        match (am, self.t_state) {
+            (AddressingMode::Immediate, _) => return Some(self.registers.pc),
+            (AddressingMode::ZeroPage, 1) | (AddressingMode::Absolute, 1)
+                | (AddressingMode::AbsoluteX, 1) | (AddressingMode::AbsoluteY, 1) => {
+                self.address = self.read_pc() as usize;
+            },
+            (AddressingMode::ZeroPageX, 1) | (AddressingMode::ZeroPageY, 1)
+                | (AddressingMode::IndexedIndirect, 1) | (AddressingMode::IndirectIndexed, 1) => {
+                self.pointer = self.read_pc();
+            },
+            (AddressingMode::ZeroPageX, 2) => {
+                let pointer = self.pointer;
+                self.dummy_read(pointer as usize);
+                self.address = pointer.wrapping_add(self.get_x()) as usize;
+            },
+            (AddressingMode::ZeroPageY, 2) => {
+                let pointer = self.pointer;
+                self.dummy_read(pointer as usize);
+                self.address = pointer.wrapping_add(self.get_y()) as usize;
+            },
+            (AddressingMode::Absolute, 2) | (AddressingMode::AbsoluteX, 2) | (AddressingMode::AbsoluteY, 2) => {
+                let adh = self.read_pc();
+                self.address = Address::combine_low_high(self.address.low_byte(), adh);
+            },
+            (AddressingMode::AbsoluteX, 3) => {
+                let x = self.get_x();
+                return self.index_cycle(x, read);
+            },
+            (AddressingMode::AbsoluteY, 3) | (AddressingMode::IndirectIndexed, 4) => {
+                let y = self.get_y();
+                return self.index_cycle(y, read);
+            },
+            (AddressingMode::IndexedIndirect, 2) => {
+                let pointer = self.pointer;
+                self.dummy_read(pointer as usize);
+                self.pointer = pointer.wrapping_add(self.get_x());
+            },
+            (AddressingMode::IndexedIndirect, 3) | (AddressingMode::IndirectIndexed, 2) => {
+                let pointer = self.pointer;
+                self.address = self.read_byte(pointer as usize) as usize;
+            },
+            (AddressingMode::IndexedIndirect, 4) | (AddressingMode::IndirectIndexed, 3) => {
+                let pointer = self.pointer.wrapping_add(1);
+                let adh = self.read_byte(pointer as usize);
+                self.address = Address::combine_low_high(self.address.low_byte(), adh);
+            },
+            _ => return Some(self.address)
+        }
+        None
+    }
+
+    // The index is added to the low byte first, and reading the address that
+    // gives takes the cycle in which the high byte would be fixed
+    fn index_cycle(&mut self, index: u8, read: bool) -> Option<usize> {
+        let (adl, adh) = (self.address.low_byte(), self.address.high_byte());
+        let address = Address::combine_low_high(adl.wrapping_add(index), adh);
+        let crossed = adl > 255 - index;
+        if read && !crossed {
+            return Some(address);
+        }
+        self.dummy_read(address);
+        self.address = if crossed { (address + 0x100) & 0xffff } else { address };
+        None
+    }
+
+    fn compare(&mut self, register: u8, operand: u8) {
+        self.registers.p.carry = register >= operand;
+        self.registers.p.zero = register == operand;
+        self.registers.p.negative = ((register.wrapping_sub(operand)) & 0x80) != 0;
+    }
+
+    fn compare_a(&mut self, operand: u8) {
+        let a = self.registers.a;
+        self.compare(a, operand);
+    }
+
+    fn compare_x(&mut self, operand: u8) {
+        let x = self.registers.x;
+        self.compare(x, operand);
+    }
+
+    fn compare_y(&mut self, operand: u8) {
+        let y = self.registers.y;
+        self.compare(y, operand);
+    }
+
+    fn bit(&mut self, operand: u8) {
+        let a = self.registers.a;
+        self.registers.p.overflow = operand & 0x40 != 0;
+        self.registers.p.negative = operand & 0x80 != 0;
+        self.registers.p.zero = a & operand == 0;
+    }
+
+    fn add(&mut self, operand: u8) {
+        let a = self.registers.a;
+        let sum = a as u16 + operand as u16 + self.registers.p.carry_into_u8() as u16;
+        let res = sum as u8;
+        self.registers.p.carry = sum > 0xff;
+        self.registers.p.overflow = same_sign(a, operand) && !same_sign(a, res);
+        self.set_a(res);
+    }
+
+    fn adc(&mut self, operand: u8) {
+        if !self.registers.p.decimal_mode {
+            return self.add(operand);
+        }
+        let a = self.registers.a as u16;
+        let b = operand as u16;
+        let c = self.registers.p.carry_into_u8() as u16;
+        let mut low = (a & 0x0f) + (b & 0x0f) + c;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut sum = (a & 0xf0) + (b & 0xf0) + low;
+        // the NMOS 6502 takes N and V from the sum before the high digit is
+        // adjusted, and Z from the binary sum
+        self.registers.p.zero = (a + b + c) & 0xff == 0;
+        self.registers.p.negative = sum & 0x80 != 0;
+        self.registers.p.overflow = !(a ^ b) & (a ^ sum) & 0x80 != 0;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        self.registers.p.carry = sum > 0xff;
+        self.registers.a = sum as u8;
+    }
+
+    fn sbc(&mut self, operand: u8) {
+        if !self.registers.p.decimal_mode {
+            return self.add(!operand);
+        }
+        let a = self.registers.a as i16;
+        let b = operand as i16;
+        let borrow = 1 - self.registers.p.carry_into_u8() as i16;
+        let mut low = (a & 0x0f) - (b & 0x0f) - borrow;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0f) - 0x10;
+        }
+        let mut difference = (a & 0xf0) - (b & 0xf0) + low;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        // all flags are those of the binary subtraction
+        self.add(!operand);
+        self.registers.a = difference as u8;
+    }
+
+    #[inline(always)]
+    fn execute_single_byte(&mut self, m: SingleByteMnemonic) -> bool {
+        match m {
+            SingleByteMnemonic::ASL => self.mod_a(Self::do_asl),
+            SingleByteMnemonic::CLC => self.registers.p.carry = false,
+            SingleByteMnemonic::CLD => self.registers.p.decimal_mode = false,
+            SingleByteMnemonic::CLI => self.registers.p.irq_disabled = false,
+            SingleByteMnemonic::CLV => self.registers.p.overflow = false,
+            SingleByteMnemonic::DEX => self.mod_x(|_, x| x.wrapping_sub(1)),
+            SingleByteMnemonic::DEY => self.mod_y(|_, y| y.wrapping_sub(1)),
+            SingleByteMnemonic::INX => self.mod_x(|_, x| x.wrapping_add(1)),
+            SingleByteMnemonic::INY => self.mod_y(|_, y| y.wrapping_add(1)),
+            SingleByteMnemonic::LSR => self.mod_a(Self::do_lsr),
+            SingleByteMnemonic::NOP => {},
+            SingleByteMnemonic::ROL => self.mod_a(Self::do_rol),
+            SingleByteMnemonic::ROR => self.mod_a(Self::do_ror),
+            SingleByteMnemonic::SEC => self.registers.p.carry = true,
+            SingleByteMnemonic::SED => self.registers.p.decimal_mode = true,
+            SingleByteMnemonic::SEI => self.registers.p.irq_disabled = true,
+            SingleByteMnemonic::TAX => self.mod_x(|this, _| this.registers.a),
+            SingleByteMnemonic::TAY => self.mod_y(|this, _| this.registers.a),
+            SingleByteMnemonic::TSX => self.mod_x(|this, _| this.s_into_byte()),
+            SingleByteMnemonic::TXA => self.mod_a(|this, _| this.registers.x),
+            SingleByteMnemonic::TXS => {
+                let x = self.registers.x;
+                self.s_from_byte(x)
+            },
+            SingleByteMnemonic::TYA => self.mod_a(|this, _| this.registers.y)
+        }
+        self.bogus_read_pc();
+        true
+    }
+
+    #[inline(always)]
+    fn execute_read(&mut self, m: ReadMnemonic, am: AddressingMode) -> bool {
+        let operand = match self.address_cycle(am, true) {
+            None => return false,
+            Some(_) if am == AddressingMode::Immediate => self.read_pc(),
+            Some(address) => self.read_byte(address)
+        };
+        match m {
+            ReadMnemonic::ADC => self.adc(operand),
+            ReadMnemonic::AND => self.mod_a(|_, a| a & operand),
+            ReadMnemonic::BIT => self.bit(operand),
+            ReadMnemonic::CMP => self.compare_a(operand),
+            ReadMnemonic::CPX => self.compare_x(operand),
+            ReadMnemonic::CPY => self.compare_y(operand),
+            ReadMnemonic::EOR => self.mod_a(|_, a| a ^ operand),
+            ReadMnemonic::LDA => self.mod_a(|_, _| operand),
+            ReadMnemonic::LDX => self.mod_x(|_, _| operand),
+            ReadMnemonic::LDY => self.mod_y(|_, _| operand),
+            ReadMnemonic::ORA => self.mod_a(|_, a| a | operand),
+            ReadMnemonic::SBC => self.sbc(operand)
+        }
+        true
+    }
+
+    fn should_branch(&self, m: BranchMnemonic) -> bool {
+        match m {
+            BranchMnemonic::BCC => !self.registers.p.carry,
+            BranchMnemonic::BCS => self.registers.p.carry,
+            BranchMnemonic::BEQ => self.registers.p.zero,
+            BranchMnemonic::BMI => self.registers.p.negative,
+            BranchMnemonic::BNE => !self.registers.p.zero,
+            BranchMnemonic::BPL => !self.registers.p.negative,
+            BranchMnemonic::BVC => !self.registers.p.overflow,
+            BranchMnemonic::BVS => self.registers.p.overflow
+        }
+    }
+
+    #[inline(always)]
+    fn execute_branch(&mut self, m: BranchMnemonic) -> bool {
+        match self.t_state {
+            1 => {
+                self.data = self.read_pc();
+                !self.should_branch(m)
+            },
+            2 => {
+                // the next opcode is read and thrown away while the offset
+                // is added to the low byte of PC
+                let pc = self.registers.pc;
+                self.dummy_read(pc);
+                self.address = pc.add_offset(self.data);
+                if self.address.on_different_page(pc) {
+                    return false;
+                }
+                self.registers.pc = self.address;
+                true
+            },
+            _ => {
+                // the target before the high byte is fixed up is read first
+                let pc = self.registers.pc;
+                self.dummy_read(Address::combine_low_high(self.address.low_byte(), pc.high_byte()));
+                self.registers.pc = self.address;
+                true
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn execute_read_modify_write(&mut self, m: RMWMnemonic, am: AddressingMode) -> bool {
+        let address = match self.address_cycle(am, false) {
+            None => return false,
+            Some(address) => address
+        };
+        match self.t_state - address_cycles_before_access(am) {
+            1 => self.data = self.read_byte(address),
+            // the NMOS 6502 writes the unmodified value back while it works
+            2 => self.write_byte(address, self.data),
+            _ => {
+                let val = self.data;
+                let res = match m {
+                    RMWMnemonic::ASL => self.do_asl(val),
+                    RMWMnemonic::DEC => val.wrapping_sub(1),
+                    RMWMnemonic::INC => val.wrapping_add(1),
+                    RMWMnemonic::LSR => self.do_lsr(val),
+                    RMWMnemonic::ROL => self.do_rol(val),
+                    RMWMnemonic::ROR => self.do_ror(val)
+                };
+                self.set_zn(res);
+                self.write_byte(address, res);
+                return true;
+            }
+        }
+        false
+    }
+
+    #[inline(always)]
+    fn execute_push(&mut self, m: PushMnemonic) -> bool {
+        if self.t_state == 1 {
+            self.bogus_read_pc();
+            return false;
+        }
+        let val = match m {
+            PushMnemonic::PHA => self.registers.a,
+            PushMnemonic::PHP => self.registers.p.to_byte(true)
+        };
+        self.push(val);
+        true
+    }
+
+    #[inline(always)]
+    fn execute_pull(&mut self, m: PullMnemonic) -> bool {
+        match self.t_state {
+            1 => self.bogus_read_pc(),
+            2 => {
+                let s = self.stack_address();
+                self.dummy_read(s);
+            },
+            _ => {
+                let val = self.pull();
+                match m {
+                    PullMnemonic::PLA => self.set_a(val),
+                    PullMnemonic::PLP => self.registers.p = StatusRegister::from_byte(val)
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    #[inline(always)]
+    fn execute_jump(&mut self, am: JumpAddressingMode) -> bool {
+        match self.t_state {
+            1 => self.data = self.read_pc(),
+            2 => {
+                let adh = self.read_pc();
+                self.address = Address::combine_low_high(self.data, adh);
+                if am == JumpAddressingMode::Absolute {
+                    self.registers.pc = self.address;
+                    return true;
+                }
+            },
+            3 => {
+                let pointer = self.address;
+                self.data = self.read_byte(pointer);
+            },
+            _ => {
+                // the pointer's high byte is fetched without carrying into
+                // the page, so JMP ($10FF) reads $10FF and $1000
+                let pointer = self.address;
+                let pch = self.read_byte(Address::combine_low_high(pointer.low_byte().wrapping_add(1), pointer.high_byte()));
+                self.registers.pc = Address::combine_low_high(self.data, pch);
+                return true;
+            }
+        }
+        false
+    }
+
+    #[inline(always)]
+    fn execute_store(&mut self, m: StoreMnemonic, am: AddressingMode) -> bool {
+        let address = match self.address_cycle(am, false) {
+            None => return false,
+            Some(address) => address
+        };
+        let val = match m {
+            StoreMnemonic::STA => self.registers.a,
+            StoreMnemonic::STX => self.registers.x,
+            StoreMnemonic::STY => self.registers.y
+        };
+        self.write_byte(address, val);
+        true
+    }
+
+    #[inline(always)]
+    fn execute_jsr(&mut self) -> bool {
+        match self.t_state {
+            1 => self.data = self.read_pc(),
+            2 => {
+                let s = self.stack_address();
+                self.dummy_read(s);
+            },
+            3 => {
+                let pch = self.registers.pc.high_byte();
+                self.push(pch);
+            },
+            4 => {
+                let pcl = self.registers.pc.low_byte();
+                self.push(pcl);
+            },
+            _ => {
+                let adh = self.read_pc();
+                self.registers.pc = Address::combine_low_high(self.data, adh);
+                #[cfg(feature = "std")]
+                if let Some(ref mut call_stack) = self.call_stack {
+                    let call_site = self.instruction_pc as u16;
+                    call_stack.call(FrameKind::Call, call_site, self.registers.pc as u16, call_site.wrapping_add(3),
+                                    self.registers.s as u8);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    #[inline(always)]
+    fn execute_rts(&mut self) -> bool {
+        match self.t_state {
+            1 => self.bogus_read_pc(),
+            2 => {
+                let s = self.stack_address();
+                self.dummy_read(s);
+            },
+            3 => self.data = self.pull(),
+            4 => {
+                let pch = self.pull();
+                self.registers.pc = Address::combine_low_high(self.data, pch);
+            },
+            _ => {
+                self.bogus_read_pc();
+                self.registers.pc = (self.registers.pc + 1) & 0xffff;
+                #[cfg(feature = "std")]
+                if let Some(ref mut call_stack) = self.call_stack {
+                    call_stack.ret(FrameKind::Call, self.instruction_pc as u16, self.registers.pc as u16,
+                                   self.registers.s as u8);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    #[inline(always)]
+    fn execute_brk(&mut self) -> bool {
+        if self.t_state == 1 {
+            // BRK skips the byte that follows it, which makes room for a signature
+            let _ = self.read_pc();
+            self.address = IRQ_VECTOR;
+            return false;
+        }
+        self.interrupt_cycle(true)
+    }
+
+    // The last five cycles of BRK and of servicing an interrupt: push PC and
+    // P, then load PC from the vector in `address`
+    #[inline(always)]
+    fn interrupt_cycle(&mut self, brk: bool) -> bool {
+        match self.t_state {
+            2 => {
+                let pch = self.registers.pc.high_byte();
+                self.push(pch);
+            },
+            3 => {
+                let pcl = self.registers.pc.low_byte();
+                self.push(pcl);
+            },
+            4 => {
+                let p = self.registers.p.to_byte(brk);
+                self.push(p);
+                self.registers.p.irq_disabled = true;
+            },
+            5 => {
+                let vector = self.address;
+                self.data = self.read_byte(vector);
+            },
+            _ => {
+                #[cfg(feature = "std")]
+                let pc = self.registers.pc;
+                let adh = self.read_byte(self.address + 1);
+                self.registers.pc = Address::combine_low_high(self.data, adh);
+                #[cfg(feature = "std")]
+                if let Some(ref mut call_stack) = self.call_stack {
+                    let call_site = self.instruction_pc as u16;
+                    call_stack.call(FrameKind::Interrupt, call_site, self.registers.pc as u16, pc as u16,
+                                    self.registers.s as u8);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // Servicing an interrupt looks like BRK, except that the first two
+    // cycles read PC without moving it
+    fn service_interrupt(&mut self, to_completion: bool) -> Result<bool, ErrorKind> {
+        if self.t_state == 1 {
+            self.bogus_read_pc();
+            self.address = if self.nmi_pending {
+                self.nmi_pending = false;
+                NMI_VECTOR
+            }
+            else {
+                IRQ_VECTOR
+            };
+            if !to_completion {
+                return Ok(false);
+            }
+            self.t_state += 1;
+        }
+        while !self.interrupt_cycle(false) {
+            if !to_completion {
+                return Ok(false);
+            }
+            self.t_state += 1;
+        }
+        Ok(true)
+    }
+
+    #[inline(always)]
+    fn execute_rti(&mut self) -> bool {
+        match self.t_state {
+            1 => self.bogus_read_pc(),
+            2 => {
+                let s = self.stack_address();
+                self.dummy_read(s);
+            },
+            3 => {
+                let p = self.pull();
+                self.registers.p = StatusRegister::from_byte(p);
+            },
+            4 => self.data = self.pull(),
+            _ => {
+                let pch = self.pull();
+                self.registers.pc = Address::combine_low_high(self.data, pch);
+                #[cfg(feature = "std")]
+                if let Some(ref mut call_stack) = self.call_stack {
+                    call_stack.ret(FrameKind::Interrupt, self.instruction_pc as u16, self.registers.pc as u16,
+                                   self.registers.s as u8);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // Decides in the cycle that fetched an undocumented opcode what happens
+    // next; returns whether that completes it
+    #[cold]
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    fn illegal_opcode(&mut self, opcode: u8) -> Result<bool, ErrorKind> {
+        let (outcome, complete) = match self.illegal_opcode_policy {
+            IllegalOpcodePolicy::Fail => return Err(dispatch::illegal(opcode)),
+            IllegalOpcodePolicy::Nop => {
+                self.handler = Self::skip_illegal_opcode;
+                ("skipped", false)
+            },
+            IllegalOpcodePolicy::Halt => {
+                self.registers.pc = self.instruction_pc;
+                self.halted = true;
+                ("halted", true)
+            },
+            #[cfg(feature = "std")]
+            IllegalOpcodePolicy::Callback(_) => {
+                let mut policy = ::core::mem::replace(&mut self.illegal_opcode_policy, IllegalOpcodePolicy::Fail);
+                let result = match policy {
+                    IllegalOpcodePolicy::Callback(ref mut callback) => callback(self, opcode),
+                    _ => unreachable!()
+                };
+                // unless the callback chose a different policy meanwhile
+                if let IllegalOpcodePolicy::Fail = self.illegal_opcode_policy {
+                    self.illegal_opcode_policy = policy;
+                }
+                result?;
+                ("emulated by callback", true)
+            },
+            #[cfg(not(feature = "std"))]
+            IllegalOpcodePolicy::_Bus(_) => unreachable!()
+        };
+        #[cfg(feature = "std")]
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.illegal_opcode(self.instruction_pc as u16, opcode, outcome);
+        }
+        Ok(complete)
+    }
+
+    fn skip_illegal_opcode(&mut self, _to_completion: bool) -> Result<bool, ErrorKind> {
+        self.bogus_read_pc();
+        Ok(true)
+    }
+
+    #[cold]
+    fn error(&mut self, kind: ErrorKind) -> Error {
+        if let ErrorKind::Halt(_) = kind {
+            // a halted processor keeps fetching the same opcode
+            self.registers.pc = self.instruction_pc;
+        }
+        #[cfg(feature = "std")]
+        let count = self.instructions.min(HISTORY_LENGTH as u64);
+        #[cfg(feature = "std")]
+        let history = (self.instructions - count..self.instructions).map(|i| {
+            let address = self.history[i as usize % HISTORY_LENGTH];
+            (address, disassemble(|a| self.peek(a), address).text)
+        }).collect();
+        Error {
+            kind,
+            pc: self.instruction_pc as u16,
+            bank: self.bus.bank(self.instruction_pc as u16),
+            registers: self.registers,
+            cycles: self.cycles,
+            #[cfg(feature = "std")]
+            history
+        }
+    }
+
+    fn opcode_at_pc(&self) -> Option<Instruction> {
+        decode(self.peek(self.registers.pc as u16))
+    }
+
+    fn run_until<F>(&mut self, cycles: u64, mut done: F) -> Result<StopReason, Error>
+        where F: FnMut(&Self, Option<Instruction>) -> bool
+    {
+        let target = self.cycles.saturating_add(cycles);
+        #[cfg(feature = "std")]
+        let mut first = true;
+        #[cfg(feature = "std")]
+        let _ = self.debugger.take_hit();
+        if self.halted {
+            return Ok(StopReason::Halted(self.registers.pc as u16));
+        }
+        while self.cycles < target {
+            let pc = self.registers.pc as u16;
+            #[cfg(feature = "std")]
+            {
+                if !first && self.debugger.has_breakpoint(pc) {
+                    return Ok(StopReason::Breakpoint(pc));
+                }
+                first = false;
+            }
+            let instruction = self.opcode_at_pc();
+            self.step()?;
+            #[cfg(feature = "std")]
+            if let Some(hit) = self.debugger.take_hit() {
+                return Ok(hit);
+            }
+            if self.halted {
+                return Ok(StopReason::Halted(pc));
+            }
+            if done(self, instruction) {
+                return Ok(StopReason::StepComplete);
+            }
+        }
+        Ok(StopReason::CyclesElapsed)
+    }
+
+    pub fn run(&mut self, cycles: u64) -> Result<StopReason, Error> {
+        self.run_until(cycles, |_, _| false)
+    }
+
+    pub fn step_over(&mut self, cycles: u64) -> Result<StopReason, Error> {
+        let s = self.registers.s;
+        let return_address = match self.opcode_at_pc() {
+            Some(Instruction::Misc(MiscMnemonic::JSR)) => (self.registers.pc + 3) & 0xffff,
+            _ => return self.run_until(cycles, |_, _| true)
+        };
+        self.run_until(cycles, |cpu, _| cpu.registers.pc == return_address && cpu.registers.s >= s)
+    }
+
+    pub fn step_out(&mut self, cycles: u64) -> Result<StopReason, Error> {
+        let s = self.registers.s;
+        self.run_until(cycles, |cpu, instruction| {
+            let returned = matches!(instruction,
+                Some(Instruction::Misc(MiscMnemonic::RTS)) | Some(Instruction::Misc(MiscMnemonic::RTI)));
+            returned && cpu.registers.s > s
+        })
+    }
+
+    #[inline]
+    fn full_cycle(&mut self, address: usize) {
+        self.cycles += self.bus.access_time(address as u16);
+    }
+
+    #[inline]
+    fn fetch_opcode(&mut self) -> u8 {
+        let pc = self.registers.pc;
+        let v = self.bus_read(pc);
+        #[cfg(feature = "std")]
+        self.log(pc, AccessKind::Opcode);
+        self.registers.pc = (pc + 1) & 0xffff;
+        v
+    }
+
+    #[inline]
+    fn read_pc(&mut self) -> u8 {
+        let pc = self.registers.pc;
+        let v = self.bus_read(pc);
+        #[cfg(feature = "std")]
+        self.log(pc, AccessKind::Operand);
+        self.registers.pc = (pc + 1) & 0xffff;
+        v
+    }
+
+    #[inline]
+    fn read_byte(&mut self, address: usize) -> u8 {
+        let v = self.bus_read(address);
+        #[cfg(feature = "std")]
+        self.log(address, AccessKind::Read);
+        v
+    }
+
+    // Reads the 6502 performs on its own while indexing or pushing; they take
+    // a cycle and are visible to watchpoints, but say nothing about the data
+    #[inline]
+    fn dummy_read(&mut self, address: usize) {
+        let _ = self.bus_read(address);
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn log(&mut self, address: usize, kind: AccessKind) {
+        if let Some(ref mut logger) = self.logger {
+            logger.log(address as u16, kind);
+        }
+    }
+
+    #[inline]
+    fn bus_read(&mut self, address: usize) -> u8 {
+        assert!(address <= 65535, "address out of bounds");
+        self.bus.synchronize(self.cycles);
+        let v = match self.bus.read(address as u16) {
+            Some(v) => v,
+            None => self.unmapped(address, Access::Read)
+        };
+        self.data_bus = v;
+        self.full_cycle(address);
+        #[cfg(feature = "std")]
+        if self.debugger.is_watching() {
+            let pc = self.instruction_pc as u16;
+            self.debugger.check_access(pc, address as u16, Access::Read, v);
+        }
+        v
+    }
+
+    #[inline]
+    fn write_byte(&mut self, address: usize, val: u8) {
+        assert!(address <= 65535, "address out of bounds");
+        self.bus.synchronize(self.cycles);
+        if !self.bus.write(address as u16, val) {
+            self.unmapped(address, Access::Write);
+        }
+        self.data_bus = val;
+        self.full_cycle(address);
+        #[cfg(feature = "std")]
+        self.log(address, AccessKind::Write);
+        #[cfg(feature = "std")]
+        if self.debugger.is_watching() {
+            let pc = self.instruction_pc as u16;
+            self.debugger.check_access(pc, address as u16, Access::Write, val);
+        }
+    }
+
+    /// Records an access nothing responded to; `step` fails with it once
+    /// the instruction is complete. A read sees the open bus.
+    #[cold]
+    fn unmapped(&mut self, address: usize, access: Access) -> u8 {
+        if self.fault.is_none() {
+            self.fault = Some(ErrorKind::UnmappedAccess(address as u16, access));
+        }
+        self.data_bus
+    }
+
+    fn stack_address(&self) -> usize {
+        0x100 | (self.registers.s & 0xff)
+    }
+
+    fn push(&mut self, val: u8) {
+        let address = self.stack_address();
+        self.write_byte(address, val);
+        self.registers.s = self.registers.s.wrapping_sub(1) & 0xff;
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.registers.s = (self.registers.s + 1) & 0xff;
+        let address = self.stack_address();
+        self.read_byte(address)
+    }
+
+    fn bogus_read_pc(&mut self) {
+        let pc = self.registers.pc;
+        self.dummy_read(pc);
+    }
+}
+
@@ -0,0 +1,592 @@
+//! A line-oriented machine language monitor. Numbers are hexadecimal, with or
+//! without a `$` or `0x` prefix. Wherever an address is expected, a symbol
+//! name (optionally `name+offset`) loaded with `sym` can be used instead.
+//! Lines starting with `;` or `#` are ignored so that scripts can be commented.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use super::{Bus, Cpu, CpuBuilder, IllegalOpcodePolicy, Ram};
+use super::assembler::assemble;
+use super::callstack::CallStack;
+use super::cdl::CodeDataLogger;
+use super::profiler::Profiler;
+use super::debugger::{StopReason, Watchpoint};
+use super::disassembler::{disassemble_with_symbols, Line};
+use super::symbols::SymbolTable;
+use super::trace::{format_line_with_symbols, Tracer};
+
+const HELP: &str = "\
+l <file> <addr>           load a binary file at addr
+d [addr] [count]          disassemble count instructions (default 16)
+m [addr] [end]            hex dump memory (default 128 bytes)
+e <addr> <byte>...        store bytes starting at addr
+f <start> <end> <byte>    fill memory
+a <addr> <instruction>    assemble one instruction at addr
+r [reg=value]...          show or set registers (a, x, y, s, p, pc)
+b [addr]                  set a breakpoint, or list breakpoints
+bd <addr>                 delete a breakpoint
+w <start> [end] [r|w|rw]  set a watchpoint (default: writes)
+wd <start> [end] [r|w|rw] delete a watchpoint
+s [count]                 step count instructions (default 1)
+n                         step over a subroutine call
+o                         step out of the current subroutine
+g [addr] [cycles]         run until a breakpoint or the cycle budget runs out
+reset                     reset the CPU through the reset vector
+ill fail|nop|halt         stop, skip or lock up on illegal opcodes
+bt                        show the call stack
+t <file>|off              write an instruction trace to file
+sym [file]                load symbols from a label file, or show how many are loaded
+cdl on|off|clear          start, stop or reset code/data logging
+cdl save|load <file>      save the code/data log, or merge a saved log into it
+cdl [start] [end]         show coverage and never accessed ranges (default 0000-FFFF)
+p on|off|clear            start, stop or reset the cycle profiler
+p [file]                  write the profile report to file, or show it
+p folded <file>           write folded stacks for flamegraph tools
+q                         quit";
+
+const DEFAULT_BUDGET: u64 = 10_000_000;
+
+pub struct Monitor<B: Bus = Ram> {
+    cpu: Cpu<B>,
+    symbols: SymbolTable,
+    next_disassembly: u16,
+    next_dump: u16,
+    quit: bool
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Monitor::new(CpuBuilder::new().build())
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("not a number: {}", text))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let value = parse_number(text)?;
+    if value > 0xffff {
+        return Err(format!("address out of range: {}", text));
+    }
+    Ok(value as u16)
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    let value = parse_number(text)?;
+    if value > 0xff {
+        return Err(format!("byte out of range: {}", text));
+    }
+    Ok(value as u8)
+}
+
+impl<B: Bus> Monitor<B> {
+    pub fn new(mut cpu: Cpu<B>) -> Self {
+        if cpu.call_stack().is_none() {
+            cpu.set_call_stack(Some(CallStack::new()));
+        }
+        let pc = cpu.registers.pc as u16;
+        Monitor { cpu, symbols: SymbolTable::new(), next_disassembly: pc, next_dump: 0, quit: false }
+    }
+
+    pub fn symbols(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        match self.symbols.resolve(text) {
+            Some(address) => Ok(address),
+            None => parse_address(text)
+        }
+    }
+
+    fn format_line(&self) -> String {
+        format_line_with_symbols(&self.cpu, Some(&self.symbols))
+    }
+
+    pub fn cpu(&mut self) -> &mut Cpu<B> {
+        &mut self.cpu
+    }
+
+    pub fn finished(&self) -> bool {
+        self.quit
+    }
+
+    pub fn run_script<R: BufRead, W: Write>(&mut self, input: R, out: &mut W, prompt: bool) -> io::Result<()> {
+        if prompt {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        for line in input.lines() {
+            let line = line?;
+            if let Err(message) = self.execute(&line, out) {
+                writeln!(out, "?{}", message)?;
+            }
+            if self.quit {
+                break;
+            }
+            if prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arguments = &words[1..];
+        let result = match words[0].to_lowercase().as_str() {
+            "l" | "load" => self.load(arguments),
+            "d" | "disassemble" => self.disassemble(arguments, out),
+            "m" | "memory" => self.dump(arguments, out),
+            "e" | "edit" => self.edit(arguments),
+            "f" | "fill" => self.fill(arguments),
+            "a" | "assemble" => self.assemble(arguments),
+            "r" | "registers" => self.registers(arguments, out),
+            "b" | "break" => self.breakpoint(arguments, out),
+            "bd" => self.delete_breakpoint(arguments),
+            "w" | "watch" => self.watchpoint(arguments, true),
+            "wd" => self.watchpoint(arguments, false),
+            "s" | "step" => self.step(arguments, out),
+            "n" | "next" => self.stop(|cpu| cpu.step_over(DEFAULT_BUDGET), out),
+            "o" | "out" => self.stop(|cpu| cpu.step_out(DEFAULT_BUDGET), out),
+            "g" | "go" => self.go(arguments, out),
+            "t" | "trace" => self.trace(arguments),
+            "reset" => {
+                self.cpu.reset();
+                self.next_disassembly = self.cpu.registers.pc as u16;
+                writeln!(out, "{}", self.format_line()).map_err(|e| e.to_string())
+            },
+            "ill" | "illegal" => self.illegal_opcodes(arguments),
+            "bt" | "backtrace" => self.backtrace(out),
+            "sym" | "symbols" => self.load_symbols(arguments, out),
+            "cdl" => self.code_data_log(arguments, out),
+            "p" | "profile" => self.profile(arguments, out),
+            "h" | "help" | "?" => writeln!(out, "{}", HELP).map_err(|e| e.to_string()),
+            "q" | "quit" | "x" => {
+                self.quit = true;
+                Ok(())
+            },
+            command => Err(format!("unknown command: {}", command))
+        };
+        result
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.cpu.peek(address)
+    }
+
+    fn poke(&mut self, address: usize, bytes: &[u8]) -> Result<(), String> {
+        for (i, &byte) in bytes.iter().enumerate() {
+            if !self.cpu.poke((address + i) as u16, byte) {
+                return Err(format!("{:04X} cannot be changed", address + i));
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if arguments.len() != 2 {
+            return Err("usage: l <file> <addr>".to_string());
+        }
+        let data = fs::read(arguments[0]).map_err(|e| format!("{}: {}", arguments[0], e))?;
+        let address = self.address(arguments[1])? as usize;
+        if address + data.len() > 0x10000 {
+            return Err("file does not fit in memory at that address".to_string());
+        }
+        self.poke(address, &data)
+    }
+
+    fn disassemble<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        let mut address = match arguments.first() {
+            Some(a) => self.address(a)?,
+            None => self.next_disassembly
+        };
+        let count = match arguments.get(1) {
+            Some(c) => parse_number(c)?,
+            None => 16
+        };
+        for _ in 0..count {
+            let mut line = disassemble_with_symbols(|a| self.peek(a), address, Some(&self.symbols));
+            // bytes the program only ever used as data are not worth decoding
+            if let Some(usage) = self.cpu.logger().map(|l| l.usage(address)) {
+                if usage.is_data() && !usage.is_code() {
+                    let byte = self.peek(address);
+                    line = Line { address, bytes: vec![byte], info: None, text: format!(".byte ${:02X}", byte) };
+                }
+            }
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            if let Some(label) = self.symbols.name_at(address) {
+                writeln!(out, "{}:", label).map_err(|e| e.to_string())?;
+            }
+            writeln!(out, "{:04X}  {:<8}  {}", line.address, bytes.join(" "), line.text)
+                .map_err(|e| e.to_string())?;
+            address = line.next_address();
+        }
+        self.next_disassembly = address;
+        Ok(())
+    }
+
+    fn dump<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        let start = match arguments.first() {
+            Some(a) => self.address(a)? as usize,
+            None => self.next_dump as usize
+        };
+        let end = match arguments.get(1) {
+            Some(e) => self.address(e)? as usize,
+            None => (start + 0x7f).min(0xffff)
+        };
+        if end < start {
+            return Err("end lies before start".to_string());
+        }
+        for row in (start..=end).step_by(16) {
+            let bytes: Vec<u8> = (row..=end.min(row + 15)).map(|a| self.peek(a as u16)).collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes.iter()
+                .map(|&b| if (0x20..0x7f).contains(&b) { b as char } else { '.' })
+                .collect();
+            writeln!(out, "{:04X}  {:<47}  {}", row, hex.join(" "), text).map_err(|e| e.to_string())?;
+        }
+        self.next_dump = ((end + 1) & 0xffff) as u16;
+        Ok(())
+    }
+
+    fn edit(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if arguments.len() < 2 {
+            return Err("usage: e <addr> <byte>...".to_string());
+        }
+        let address = self.address(arguments[0])? as usize;
+        let bytes = arguments[1..].iter().map(|b| parse_byte(b)).collect::<Result<Vec<u8>, String>>()?;
+        if address + bytes.len() > 0x10000 {
+            return Err("edit runs past the end of memory".to_string());
+        }
+        self.poke(address, &bytes)
+    }
+
+    fn fill(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if arguments.len() != 3 {
+            return Err("usage: f <start> <end> <byte>".to_string());
+        }
+        let start = self.address(arguments[0])? as usize;
+        let end = self.address(arguments[1])? as usize;
+        let value = parse_byte(arguments[2])?;
+        if end < start {
+            return Err("end lies before start".to_string());
+        }
+        self.poke(start, &vec![value; end - start + 1])
+    }
+
+    fn assemble(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if arguments.len() < 2 {
+            return Err("usage: a <addr> <instruction>".to_string());
+        }
+        let address = self.address(arguments[0])?;
+        let instruction = arguments[1..].join(" ");
+        let program = assemble(&format!(".org ${:04X}\n {}", address, instruction))
+            .map_err(|e| e.to_string())?;
+        program.load_into(&mut self.cpu);
+        let length = program.segments.iter().map(|s| s.bytes.len()).sum::<usize>();
+        self.next_disassembly = address.wrapping_add(length as u16);
+        Ok(())
+    }
+
+    fn registers<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        for assignment in arguments {
+            let mut parts = assignment.splitn(2, '=');
+            let name = parts.next().unwrap_or("").to_lowercase();
+            let value = parts.next().ok_or_else(|| format!("expected reg=value, got {}", assignment))?;
+            let r = &mut self.cpu.registers;
+            match name.as_str() {
+                "a" => r.a = parse_byte(value)?,
+                "x" => r.x = parse_byte(value)?,
+                "y" => r.y = parse_byte(value)?,
+                "s" | "sp" => r.s = parse_byte(value)? as usize,
+                "p" => r.p = parse_byte(value)?.into(),
+                "pc" => {
+                    let address = self.symbols.resolve(value).map_or_else(|| parse_address(value), Ok)?;
+                    r.pc = address as usize;
+                    self.next_disassembly = address;
+                    // the shadow stack describes how we got to the old PC
+                    if let Some(call_stack) = self.cpu.call_stack.as_mut() {
+                        call_stack.clear();
+                    }
+                },
+                _ => return Err(format!("unknown register: {}", name))
+            }
+        }
+        writeln!(out, "{}", self.format_line()).map_err(|e| e.to_string())
+    }
+
+    fn breakpoint<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        match arguments.first() {
+            Some(a) => {
+                let address = self.address(a)?;
+                self.cpu.debugger().add_breakpoint(address);
+                Ok(())
+            },
+            None => {
+                let breakpoints: Vec<u16> = self.cpu.debugger().breakpoints().collect();
+                for address in breakpoints {
+                    let name = self.symbols.describe(address).unwrap_or_default();
+                    writeln!(out, "{:04X}  {}", address, name).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn delete_breakpoint(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let address = self.address(arguments.first().ok_or("usage: bd <addr>")?)?;
+        if self.cpu.debugger().remove_breakpoint(address) {
+            Ok(())
+        }
+        else {
+            Err(format!("no breakpoint at {:04X}", address))
+        }
+    }
+
+    fn watchpoint(&mut self, arguments: &[&str], insert: bool) -> Result<(), String> {
+        let start = self.address(arguments.first().ok_or("usage: w <start> [end] [r|w|rw]")?)?;
+        let mut end = start;
+        let mut kind = "w";
+        for argument in &arguments[1..] {
+            match *argument {
+                "r" | "w" | "rw" => kind = argument,
+                _ => end = self.address(argument)?
+            }
+        }
+        let watchpoint = match kind {
+            "r" => Watchpoint::read(start, end),
+            "rw" => Watchpoint::access(start, end),
+            _ => Watchpoint::write(start, end)
+        };
+        if insert {
+            self.cpu.debugger().add_watchpoint(watchpoint);
+        }
+        else if !self.cpu.debugger().remove_watchpoint(watchpoint) {
+            return Err("no such watchpoint".to_string());
+        }
+        Ok(())
+    }
+
+    fn step<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        let count = match arguments.first() {
+            Some(c) => parse_number(c)?,
+            None => 1
+        };
+        for _ in 0..count {
+            writeln!(out, "{}", self.format_line()).map_err(|e| e.to_string())?;
+            if let Err(e) = self.cpu.step() {
+                return Err(format!("{}\n{}", e, self.backtrace_text()));
+            }
+        }
+        self.next_disassembly = self.cpu.registers.pc as u16;
+        Ok(())
+    }
+
+    fn stop<W, F>(&mut self, f: F, out: &mut W) -> Result<(), String>
+        where W: Write, F: FnOnce(&mut Cpu<B>) -> Result<StopReason, super::Error>
+    {
+        let reason = match f(&mut self.cpu) {
+            Ok(reason) => reason,
+            Err(e) => return Err(format!("{}\n{}", e, self.backtrace_text()))
+        };
+        self.next_disassembly = self.cpu.registers.pc as u16;
+        let message = match reason {
+            StopReason::CyclesElapsed => "cycle budget exhausted".to_string(),
+            StopReason::StepComplete => String::new(),
+            StopReason::Breakpoint(address) => match self.symbols.describe(address) {
+                Some(name) => format!("breakpoint at {:04X} ({})\n{}", address, name, self.backtrace_text()),
+                None => format!("breakpoint at {:04X}\n{}", address, self.backtrace_text())
+            },
+            StopReason::Halted(address) => format!("halted by illegal opcode at {:04X}", address),
+            StopReason::Watchpoint { pc, address, access, value } =>
+                format!("{:?} of {:02X} at {:04X} by instruction at {:04X}", access, value, address, pc)
+        };
+        if !message.is_empty() {
+            writeln!(out, "{}", message).map_err(|e| e.to_string())?;
+        }
+        writeln!(out, "{}", self.format_line()).map_err(|e| e.to_string())
+    }
+
+    fn go<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        if let Some(a) = arguments.first() {
+            self.cpu.registers.pc = self.address(a)? as usize;
+            if let Some(call_stack) = self.cpu.call_stack_mut() {
+                call_stack.clear();
+            }
+        }
+        let budget = match arguments.get(1) {
+            Some(c) => parse_number(c)? as u64,
+            None => DEFAULT_BUDGET
+        };
+        self.stop(|cpu| cpu.run(budget), out)
+    }
+
+    fn illegal_opcodes(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let policy = match arguments.first() {
+            Some(&"fail") => IllegalOpcodePolicy::Fail,
+            Some(&"nop") => IllegalOpcodePolicy::Nop,
+            Some(&"halt") => IllegalOpcodePolicy::Halt,
+            _ => return Err("usage: ill fail|nop|halt".to_string())
+        };
+        self.cpu.set_illegal_opcode_policy(policy);
+        Ok(())
+    }
+
+    fn trace(&mut self, arguments: &[&str]) -> Result<(), String> {
+        match arguments.first() {
+            Some(&"off") => {
+                self.cpu.set_tracer(None);
+                Ok(())
+            },
+            Some(path) => {
+                let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+                let tracer = Tracer::new(BufWriter::new(file)).with_symbols(self.symbols.clone());
+                self.cpu.set_tracer(Some(tracer));
+                Ok(())
+            },
+            None => Err("usage: t <file>|off".to_string())
+        }
+    }
+
+    fn load_symbols<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        if let Some(path) = arguments.first() {
+            let table = SymbolTable::load(path).map_err(|e| e.to_string())?;
+            self.symbols.merge(&table);
+        }
+        writeln!(out, "{} symbols", self.symbols.len()).map_err(|e| e.to_string())
+    }
+
+    fn code_data_log<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        match arguments.first().cloned() {
+            Some("on") => {
+                if self.cpu.logger().is_none() {
+                    self.cpu.set_logger(Some(CodeDataLogger::new()));
+                }
+                Ok(())
+            },
+            Some("off") => {
+                self.cpu.set_logger(None);
+                Ok(())
+            },
+            Some("clear") => {
+                if let Some(mut logger) = self.cpu.set_logger(None) {
+                    logger.clear();
+                    self.cpu.set_logger(Some(logger));
+                }
+                Ok(())
+            },
+            Some("save") => {
+                let path = arguments.get(1).ok_or("usage: cdl save <file>")?;
+                let logger = self.cpu.logger().ok_or("code/data logging is off")?;
+                logger.save(path).map_err(|e| format!("{}: {}", path, e))
+            },
+            Some("load") => {
+                let path = arguments.get(1).ok_or("usage: cdl load <file>")?;
+                let saved = CodeDataLogger::load(path).map_err(|e| format!("{}: {}", path, e))?;
+                let mut logger = self.cpu.set_logger(None).unwrap_or_default();
+                logger.merge(&saved);
+                self.cpu.set_logger(Some(logger));
+                Ok(())
+            },
+            _ => self.coverage(arguments, out)
+        }
+    }
+
+    fn coverage<W: Write>(&self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        let logger = self.cpu.logger().ok_or("code/data logging is off")?;
+        let start = match arguments.first() {
+            Some(a) => self.address(a)? as usize,
+            None => 0
+        };
+        let end = match arguments.get(1) {
+            Some(a) => self.address(a)? as usize,
+            None => 0xffff
+        };
+        if end < start {
+            return Err("end lies before start".to_string());
+        }
+        let coverage = logger.coverage(start..end + 1);
+        writeln!(out, "{} bytes: {} code, {} data, {} never accessed",
+                 coverage.total, coverage.code, coverage.data, coverage.unused)
+            .map_err(|e| e.to_string())?;
+        for range in logger.unused_ranges(start..end + 1) {
+            writeln!(out, "{:04X}-{:04X}", range.start, range.end - 1).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn profile<W: Write>(&mut self, arguments: &[&str], out: &mut W) -> Result<(), String> {
+        match arguments.first().cloned() {
+            Some("on") => {
+                if self.cpu.profiler().is_none() {
+                    self.cpu.set_profiler(Some(Profiler::new()));
+                }
+                Ok(())
+            },
+            Some("off") => {
+                self.cpu.set_profiler(None);
+                Ok(())
+            },
+            Some("clear") => {
+                if let Some(mut profiler) = self.cpu.set_profiler(None) {
+                    profiler.clear();
+                    self.cpu.set_profiler(Some(profiler));
+                }
+                Ok(())
+            },
+            Some("folded") => {
+                let path = arguments.get(1).ok_or("usage: p folded <file>")?;
+                let profiler = self.cpu.profiler().ok_or("profiling is off")?;
+                let mut file = BufWriter::new(File::create(path).map_err(|e| format!("{}: {}", path, e))?);
+                profiler.write_folded(&mut file, Some(&self.symbols))
+                    .and_then(|_| file.flush())
+                    .map_err(|e| format!("{}: {}", path, e))
+            },
+            Some(path) => {
+                let profiler = self.cpu.profiler().ok_or("profiling is off")?;
+                let mut file = BufWriter::new(File::create(path).map_err(|e| format!("{}: {}", path, e))?);
+                profiler.write_report(&mut file, Some(&self.symbols))
+                    .and_then(|_| file.flush())
+                    .map_err(|e| format!("{}: {}", path, e))
+            },
+            None => {
+                let profiler = self.cpu.profiler().ok_or("profiling is off")?;
+                profiler.write_report(out, Some(&self.symbols)).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    fn backtrace_text(&self) -> String {
+        let pc = self.cpu.registers.pc as u16;
+        match self.cpu.call_stack() {
+            Some(call_stack) => call_stack.backtrace(pc, Some(&self.symbols)).trim_end().to_string(),
+            None => "the call stack is not kept".to_string()
+        }
+    }
+
+    fn backtrace<W: Write>(&mut self, out: &mut W) -> Result<(), String> {
+        writeln!(out, "{}", self.backtrace_text()).map_err(|e| e.to_string())?;
+        let mismatches = match self.cpu.call_stack() {
+            Some(call_stack) => call_stack.mismatches(),
+            None => return Ok(())
+        };
+        if let Some(last) = mismatches.back() {
+            let expected = match last.expected {
+                Some(address) => format!("{:04X}", address),
+                None => "nothing".to_string()
+            };
+            writeln!(out, "{} unbalanced returns, the last at {:04X} went to {:04X} instead of {}",
+                     mismatches.len(), last.pc, last.actual, expected)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
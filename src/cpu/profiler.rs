@@ -0,0 +1,223 @@
+//! A cycle profiler. Every cycle an instruction takes is charged to the
+//! address of that instruction and to the subroutine it runs in. Subroutines
+//! are entered through JSR, and interrupt handlers through BRK, IRQ and NMI,
+//! so each cycle also belongs to a chain of callers. A routine is left once
+//! the stack pointer rises above where it was on entry, by RTS or RTI or by
+//! code that drops its return address, so that the chain cannot outgrow the
+//! stack. Those chains are kept as a call tree and can be written as folded
+//! stacks, the input format of flamegraph.pl, inferno and speedscope.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use super::instruction::{decode, Instruction, MiscMnemonic};
+use super::symbols::SymbolTable;
+
+struct Frame {
+    routine: u16,
+    parent: usize,
+    children: HashMap<u16, usize>,
+    cycles: u64
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub address: u16,
+    pub calls: u64,
+    pub self_cycles: u64,
+    pub total_cycles: u64
+}
+
+pub struct Profiler {
+    per_pc: Vec<u64>,
+    calls: HashMap<u16, u64>,
+    frames: Vec<Frame>,
+    /// The routines running now, innermost last, each with the stack pointer
+    /// as it was after entering it.
+    active: Vec<(usize, u8)>,
+    total: u64
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            per_pc: vec![0; 0x10000],
+            calls: HashMap::new(),
+            frames: Vec::new(),
+            active: Vec::new(),
+            total: 0
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Profiler::new();
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total
+    }
+
+    pub fn cycles_at(&self, pc: u16) -> u64 {
+        self.per_pc[pc as usize]
+    }
+
+    /// Charges the cycles of the instruction at `pc` and follows any change
+    /// of subroutine it caused; `next_pc` and `s` are the PC and stack
+    /// pointer after it executed.
+    pub fn record(&mut self, pc: u16, opcode: u8, cycles: u64, next_pc: u16, s: u8) {
+        let frame = self.current(pc);
+        self.per_pc[pc as usize] += cycles;
+        self.frames[frame].cycles += cycles;
+        self.total += cycles;
+        self.leave(s);
+        if let Some(Instruction::Misc(MiscMnemonic::JSR)) | Some(Instruction::Misc(MiscMnemonic::BRK)) = decode(opcode) {
+            self.enter(next_pc, s);
+        }
+    }
+
+    /// Called when the CPU takes an IRQ or NMI at `pc`, which enters `handler`
+    /// without executing an instruction. The cycles of the interrupt sequence
+    /// are charged to the interrupted routine, like those of a JSR.
+    pub fn interrupt(&mut self, pc: u16, handler: u16, cycles: u64, s: u8) {
+        let frame = self.current(pc);
+        self.frames[frame].cycles += cycles;
+        self.total += cycles;
+        self.leave(s);
+        self.enter(handler, s);
+    }
+
+    /// The frame of the routine running now; the first instruction profiled
+    /// stands in for the outermost routine.
+    fn current(&mut self, pc: u16) -> usize {
+        if self.frames.is_empty() {
+            self.frames.push(Frame { routine: pc, parent: 0, children: HashMap::new(), cycles: 0 });
+        }
+        self.active.last().map_or(0, |&(frame, _)| frame)
+    }
+
+    fn enter(&mut self, routine: u16, s: u8) {
+        let parent = self.active.last().map_or(0, |&(frame, _)| frame);
+        let next = self.frames.len();
+        let frame = *self.frames[parent].children.entry(routine).or_insert(next);
+        if frame == next {
+            self.frames.push(Frame { routine, parent, children: HashMap::new(), cycles: 0 });
+        }
+        *self.calls.entry(routine).or_insert(0) += 1;
+        self.active.push((frame, s));
+    }
+
+    /// Leaves every routine whose return address lies below the stack
+    /// pointer. Leaving the outermost routine, e.g. after the stack was
+    /// rearranged by hand, leaves us where we are.
+    fn leave(&mut self, s: u8) {
+        while self.active.last().is_some_and(|&(_, entered)| entered < s) {
+            self.active.pop();
+        }
+    }
+
+    fn path(&self, mut frame: usize) -> Vec<u16> {
+        let mut path = vec![self.frames[frame].routine];
+        while frame != 0 {
+            frame = self.frames[frame].parent;
+            path.push(self.frames[frame].routine);
+        }
+        path.reverse();
+        path
+    }
+
+    fn stats(&self, routine: u16) -> RoutineStats {
+        RoutineStats {
+            address: routine,
+            calls: self.calls.get(&routine).cloned().unwrap_or(0),
+            ..Default::default()
+        }
+    }
+
+    /// Per-routine statistics, most expensive (inclusive) first. Recursive
+    /// calls only count once towards a routine's total.
+    pub fn routines(&self) -> Vec<RoutineStats> {
+        let mut stats: HashMap<u16, RoutineStats> = HashMap::new();
+        for index in 0..self.frames.len() {
+            let cycles = self.frames[index].cycles;
+            let mut path = self.path(index);
+            let routine = path.pop().unwrap();
+            stats.entry(routine).or_insert_with(|| self.stats(routine)).self_cycles += cycles;
+            if !path.contains(&routine) {
+                stats.get_mut(&routine).unwrap().total_cycles += cycles;
+            }
+            for (i, &caller) in path.iter().enumerate() {
+                if !path[..i].contains(&caller) && caller != routine {
+                    stats.entry(caller).or_insert_with(|| self.stats(caller)).total_cycles += cycles;
+                }
+            }
+        }
+        let mut routines: Vec<RoutineStats> = stats.into_values().collect();
+        routines.sort_by(|a, b| b.total_cycles.cmp(&a.total_cycles)
+            .then(b.self_cycles.cmp(&a.self_cycles))
+            .then(a.address.cmp(&b.address)));
+        routines
+    }
+
+    /// The `count` addresses that used the most cycles, most expensive first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(u16, u64)> {
+        let mut spots: Vec<(u16, u64)> = self.per_pc.iter().enumerate()
+            .filter(|&(_, &cycles)| cycles > 0)
+            .map(|(pc, &cycles)| (pc as u16, cycles))
+            .collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(count);
+        spots
+    }
+
+    pub fn write_report<W: Write>(&self, out: &mut W, symbols: Option<&SymbolTable>) -> io::Result<()> {
+        let percent = |cycles: u64| if self.total == 0 { 0.0 } else { cycles as f64 * 100.0 / self.total as f64 };
+        writeln!(out, "{} cycles profiled", self.total)?;
+        writeln!(out)?;
+        writeln!(out, "{:<24} {:>8} {:>12} {:>6} {:>12} {:>6}",
+                 "routine", "calls", "self", "%", "total", "%")?;
+        for routine in self.routines() {
+            writeln!(out, "{:<24} {:>8} {:>12} {:>6.2} {:>12} {:>6.2}",
+                     name(routine.address, symbols), routine.calls,
+                     routine.self_cycles, percent(routine.self_cycles),
+                     routine.total_cycles, percent(routine.total_cycles))?;
+        }
+        writeln!(out)?;
+        writeln!(out, "{:<24} {:>12} {:>6}", "address", "cycles", "%")?;
+        for (pc, cycles) in self.hot_spots(20) {
+            let label = match symbols.and_then(|s| s.describe(pc)) {
+                Some(label) => format!("{:04X} {}", pc, label),
+                None => format!("{:04X}", pc)
+            };
+            writeln!(out, "{:<24} {:>12} {:>6.2}", label, cycles, percent(cycles))?;
+        }
+        Ok(())
+    }
+
+    /// Writes one `caller;callee;... cycles` line per distinct call chain.
+    pub fn write_folded<W: Write>(&self, out: &mut W, symbols: Option<&SymbolTable>) -> io::Result<()> {
+        let mut lines: Vec<(String, u64)> = (0..self.frames.len())
+            .filter(|&index| self.frames[index].cycles > 0)
+            .map(|index| {
+                let names: Vec<String> = self.path(index).into_iter().map(|r| name(r, symbols)).collect();
+                (names.join(";"), self.frames[index].cycles)
+            })
+            .collect();
+        lines.sort();
+        for (stack, cycles) in lines {
+            writeln!(out, "{} {}", stack, cycles)?;
+        }
+        Ok(())
+    }
+}
+
+fn name(routine: u16, symbols: Option<&SymbolTable>) -> String {
+    match symbols.and_then(|s| s.name_at(routine)) {
+        Some(name) => name.to_string(),
+        None => format!("${:04X}", routine)
+    }
+}
@@ -0,0 +1,238 @@
+//! Symbol tables loaded from the label files of common toolchains:
+//!
+//! * ca65/ld65 debug info (`--dbgfile`), using the `sym` records
+//! * VICE label files as written by `ld65 -Ln` (`al 008000 .reset`)
+//! * WLA-DX `.sym` files, using the `[labels]` section
+//! * plain `name = $addr` lists
+//!
+//! Addresses are kept as 16-bit CPU addresses; bank numbers in WLA-DX files
+//! and the upper byte of 24-bit values are dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::assembler::Program;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Io(String),
+    Syntax(usize, String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref message) => write!(f, "{}", message),
+            Error::Syntax(line, ref text) => write!(f, "line {}: cannot read a symbol from `{}`", line, text)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    by_name: HashMap<String, u16>,
+    by_address: BTreeMap<u16, String>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ca65,
+    Vice,
+    WlaDx,
+    Simple
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SymbolTable, Error> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|e: io::Error| Error::Io(format!("{}: {}", path.as_ref().display(), e)))?;
+        SymbolTable::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolTable, Error> {
+        let mut table = SymbolTable::new();
+        table.parse_into(text, detect_format(text))?;
+        Ok(table)
+    }
+
+    pub fn parse_into(&mut self, text: &str, format: Format) -> Result<(), Error> {
+        let mut in_labels = false;
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            let syntax = || Error::Syntax(number, line.to_string());
+            match format {
+                Format::Ca65 => {
+                    if line.starts_with("sym") && line[3..].starts_with(char::is_whitespace) {
+                        let fields = ca65_fields(line[3..].trim());
+                        let name = fields.get("name").ok_or_else(syntax)?;
+                        // imported symbols have no value of their own
+                        if let Some(value) = fields.get("val") {
+                            let value = value.trim_start_matches("0x");
+                            let address = u32::from_str_radix(value, 16).map_err(|_| syntax())?;
+                            self.insert(name.trim_matches('"'), address as u16);
+                        }
+                    }
+                },
+                Format::Vice => {
+                    let words: Vec<&str> = line.split_whitespace().collect();
+                    if words.len() == 3 && words[0] == "al" {
+                        let value = words[1].rsplit(':').next().unwrap();
+                        let address = u32::from_str_radix(value, 16).map_err(|_| syntax())?;
+                        self.insert(words[2].trim_start_matches('.'), address as u16);
+                    }
+                },
+                Format::WlaDx => {
+                    let line = line.split(';').next().unwrap().trim();
+                    if line.starts_with('[') {
+                        in_labels = line == "[labels]";
+                        continue;
+                    }
+                    if !in_labels || line.is_empty() {
+                        continue;
+                    }
+                    let mut words = line.split_whitespace();
+                    let location = words.next().ok_or_else(syntax)?;
+                    let name = words.next().ok_or_else(syntax)?;
+                    let value = location.rsplit(':').next().unwrap();
+                    let address = u16::from_str_radix(value, 16).map_err(|_| syntax())?;
+                    self.insert(name, address);
+                },
+                Format::Simple => {
+                    let line = line.split(';').next().unwrap().trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let mut parts = line.splitn(2, '=');
+                    let name = parts.next().unwrap().trim();
+                    let value = parts.next().ok_or_else(syntax)?.trim();
+                    let address = parse_value(value).ok_or_else(syntax)?;
+                    if name.is_empty() {
+                        return Err(syntax());
+                    }
+                    self.insert(name, address);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(old) = self.by_name.insert(name.to_string(), address) {
+            if self.by_address.get(&old).map(|n| n == name).unwrap_or(false) {
+                self.by_address.remove(&old);
+            }
+        }
+        self.by_address.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    pub fn merge(&mut self, other: &SymbolTable) {
+        for (name, &address) in &other.by_name {
+            self.insert(name, address);
+        }
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).cloned()
+    }
+
+    pub fn name_at(&self, address: u16) -> Option<&str> {
+        self.by_address.get(&address).map(|s| s.as_str())
+    }
+
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (&base, name) = self.by_address.range(..=address).next_back()?;
+        match address - base {
+            0 => Some(name.clone()),
+            offset if offset < 0x100 => Some(format!("{}+{}", name, offset)),
+            _ => None
+        }
+    }
+
+    pub fn resolve(&self, expression: &str) -> Option<u16> {
+        let (name, offset) = match expression.find('+') {
+            Some(plus) => (&expression[..plus], parse_value(&expression[plus + 1..])?),
+            None => (expression, 0)
+        };
+        self.address_of(name.trim()).map(|a| a.wrapping_add(offset))
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.by_name.iter().map(|(n, &a)| (n.as_str(), a))
+    }
+}
+
+impl<'a> From<&'a Program> for SymbolTable {
+    fn from(program: &'a Program) -> Self {
+        let mut table = SymbolTable::new();
+        for (name, &address) in &program.symbols {
+            table.insert(name, address);
+        }
+        table
+    }
+}
+
+pub fn detect_format(text: &str) -> Format {
+    let mut lines = text.lines().map(|l| l.trim()).filter(|l| !l.is_empty());
+    if text.lines().any(|l| l.trim() == "[labels]") {
+        return Format::WlaDx;
+    }
+    match lines.next() {
+        Some(l) if l.starts_with("version") && l.contains("major=") => Format::Ca65,
+        Some(l) if l.starts_with("al ") => Format::Vice,
+        _ => Format::Simple
+    }
+}
+
+fn parse_value(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let value = if let Some(hex) = text.strip_prefix('$') {
+        u32::from_str_radix(hex, 16).ok()?
+    }
+    else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()?
+    }
+    else {
+        text.parse::<u32>().ok()?
+    };
+    Some(value as u16)
+}
+
+fn ca65_fields(text: &str) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let eq = match rest.find('=') {
+            Some(eq) => eq,
+            None => break
+        };
+        let key = rest[..eq].trim();
+        let value_start = &rest[eq + 1..];
+        let end = if let Some(quoted) = value_start.strip_prefix('"') {
+            quoted.find('"').map(|q| q + 2).unwrap_or(value_start.len())
+        }
+        else {
+            value_start.find(',').unwrap_or(value_start.len())
+        };
+        fields.insert(key, &value_start[..end]);
+        rest = value_start[end..].trim_start_matches(',');
+    }
+    fields
+}
@@ -0,0 +1,97 @@
+//! The assembler the other tests write their programs with.
+
+use super::super::assembler::{assemble, Error, Segment};
+
+fn bytes(source: &str) -> Vec<Segment> {
+    assemble(source).unwrap_or_else(|e| panic!("{}", e)).segments
+}
+
+#[test]
+fn assembles_every_addressing_mode() {
+    let segments = bytes("
+        .org $0200
+start:  NOP
+        ASL A
+        ASL
+        LDA #$12
+        LDA $34
+        LDA $34,X
+        LDX $34,Y
+        LDA $1234
+        LDA $1234,X
+        LDA $1234,Y
+        LDA ($34,X)
+        LDA ($34),Y
+        JMP ($1234)
+        LDA $12,Y       ; there is no zero page,Y form of LDA
+        BNE start
+    ");
+    assert_eq!(segments, vec![Segment { origin: 0x200, bytes: vec![
+        0xea,
+        0x0a,
+        0x0a,
+        0xa9, 0x12,
+        0xa5, 0x34,
+        0xb5, 0x34,
+        0xb6, 0x34,
+        0xad, 0x34, 0x12,
+        0xbd, 0x34, 0x12,
+        0xb9, 0x34, 0x12,
+        0xa1, 0x34,
+        0xb1, 0x34,
+        0x6c, 0x34, 0x12,
+        0xb9, 0x12, 0x00,
+        0xd0, 0xe0
+    ] }]);
+}
+
+#[test]
+fn forward_references_resolve_in_the_second_pass() {
+    let program = assemble("
+        .org $0300
+        JMP later
+        LDA zero        ; not known yet, so assumed to be absolute
+        BEQ later
+        NOP
+later:  RTS
+zero = $10
+    ").unwrap();
+    assert_eq!(program.symbol("later"), Some(0x309));
+    assert_eq!(program.segments[0].bytes,
+               vec![0x4c, 0x09, 0x03, 0xad, 0x10, 0x00, 0xf0, 0x01, 0xea, 0x60]);
+}
+
+#[test]
+fn branches_must_stay_within_a_signed_byte() {
+    assert_eq!(assemble(".org $0200\n BNE far\n .org $0281\nfar: RTS").unwrap().segments[0].bytes,
+               vec![0xd0, 0x7f]);
+    assert_eq!(assemble(".org $0200\n BNE far\n .org $0282\nfar: RTS").unwrap_err(),
+               Error::BranchOutOfRange(2, 128));
+    assert_eq!(assemble(".org $0182\nback: RTS\n .org $0200\n BNE back").unwrap().segments[1].bytes,
+               vec![0xd0, 0x80]);
+    assert_eq!(assemble(".org $0181\nback: RTS\n .org $0200\n BNE back").unwrap_err(),
+               Error::BranchOutOfRange(4, -129));
+}
+
+#[test]
+fn data_directives() {
+    let program = assemble("
+        .org $1000
+        .byte 1, $ff, -1, \"hi\", 'A'
+table:  .word $1234, table, <table
+    ").unwrap();
+    assert_eq!(program.segments[0].bytes,
+               vec![0x01, 0xff, 0xff, 0x68, 0x69, 0x41, 0x34, 0x12, 0x06, 0x10, 0x06, 0x00]);
+    assert_eq!(assemble(".byte 256").unwrap_err(), Error::ValueOutOfRange(1, 256));
+    assert_eq!(assemble(".word \"text\"").unwrap_err(), Error::Syntax(1, "\"text\"".to_string()));
+}
+
+#[test]
+fn arithmetic_errors_are_reported() {
+    let error = assemble("LDA #$7FFFFFFFFFFFFFFF*2").unwrap_err();
+    assert_eq!(error, Error::Arithmetic(1, "$7FFFFFFFFFFFFFFF*2".to_string()));
+    assert_eq!(error.to_string(), "line 1: `$7FFFFFFFFFFFFFFF*2` overflows or divides by zero");
+    assert!(matches!(assemble("LDA #-(-$7FFFFFFFFFFFFFFF-1)"), Err(Error::Arithmetic(1, _))));
+    assert!(matches!(assemble("x = 1/0"), Err(Error::Arithmetic(1, _))));
+    assert!(matches!(assemble("LDA #$7FFFFFFFFFFFFFFF+1"), Err(Error::Arithmetic(1, _))));
+}
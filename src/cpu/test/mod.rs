@@ -0,0 +1,1121 @@
+mod assembler;
+mod json;
+mod monitor;
+mod single_step;
+mod tracediff;
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use super::{Bus, Cpu, CpuBuilder, ErrorKind, IllegalOpcodePolicy, Memory, Ram, Registers, StatusRegister};
+use super::assembler::{assemble, Program};
+use super::callstack::{CallStack, FrameKind, Mismatch};
+use super::debugger::{Access, StopReason, Watchpoint};
+use super::cdl::{CodeDataLogger, Usage};
+use super::gdb;
+use super::dispatch::Variant;
+use super::fuzz::{Reference, Rng, State};
+use super::instruction::{decode, OperandMode};
+use super::metadata::OPCODES;
+use super::profiler::Profiler;
+use super::symbols::{detect_format, Format, SymbolTable};
+use super::trace::{format_line, format_line_with_symbols, Tracer};
+use scheduler::{Component, Machine, Region, Scheduler};
+
+struct GdbClient {
+    stream: TcpStream
+}
+
+impl GdbClient {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+');
+        if packet == "k" {
+            return String::new();
+        }
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte)
+            }
+        }
+        self.read_byte();
+        self.read_byte();
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+}
+
+#[test]
+fn gdb_stub_serves_a_loopback_client() {
+    let program = assemble("
+        .org $0200
+start:  LDX #$ff
+        TXS
+        JSR sub
+        STA $10
+halt:   BNE halt
+sub:    LDA #$42
+        RTS
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers::default());
+    program.load_into(&mut cpu);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut gdb = GdbClient { stream };
+        let mut replies = Vec::new();
+        for packet in &["qSupported:swbreak+", "?", "P5=0002", "g", "s", "p0",
+                        "Z0,20a,1", "c", "p5", "Z2,10,1", "c", "m0010,2", "M0010,2:abcd",
+                        "m0010,2", "m0,2001", "Z2,10,0", "Z2,10,10001", "Z2,fff0,10000",
+                        "z2,fff0,10000"] {
+            replies.push(gdb.request(packet));
+        }
+        // interrupt the endless loop at halt
+        gdb.stream.write_all(b"$c#63").unwrap();
+        assert_eq!(gdb.read_byte(), b'+');
+        thread::sleep(Duration::from_millis(10));
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.read_byte(), b'$');
+        let mut reply = [0; 6];
+        gdb.stream.read_exact(&mut reply).unwrap();
+        gdb.stream.write_all(b"+").unwrap();
+        assert_eq!(&reply, b"S02#b5");
+        replies.push(gdb.request("k"));
+        replies
+    });
+    let (stream, _) = listener.accept().unwrap();
+    gdb::serve(&mut cpu, stream).unwrap();
+
+    let replies = client.join().unwrap();
+    assert!(replies[0].contains("swbreak+"));
+    assert_eq!(replies[1], "S05");
+    assert_eq!(replies[2], "OK");
+    assert_eq!(replies[3], "00000000300002");
+    assert_eq!(replies[4], "S05");
+    assert_eq!(replies[5], "00");
+    assert_eq!(replies[7], "T05swbreak:;");
+    assert_eq!(replies[8], "0a02");
+    assert_eq!(replies[10], "T05watch:10;");
+    assert_eq!(replies[11], "4200");
+    assert_eq!(replies[13], "abcd");
+    assert_eq!(replies[14], "E01");
+    assert_eq!(replies[15], "E01");
+    assert_eq!(replies[16], "E01");
+    assert_eq!(replies[17], "OK");
+    assert_eq!(replies[18], "OK");
+}
+
+#[test]
+fn symbol_files_are_recognised_and_parsed() {
+    let ca65 = "version\tmajor=2,minor=0\n\
+                sym\tid=0,name=\"reset\",addrsize=absolute,val=0x8000,type=lab\n\
+                sym\tid=1,name=\"ext\",addrsize=absolute,type=imp\n";
+    let vice = "al 008003 .nmi\nal 00FFFA .vectors\n";
+    let wla = "[labels]\n00:8006 irq ; handler\n[definitions]\n00000001 ONE\n";
+    let simple = "; comment\nptr = $10\ncount = 32\n";
+    assert_eq!(detect_format(ca65), Format::Ca65);
+    assert_eq!(detect_format(vice), Format::Vice);
+    assert_eq!(detect_format(wla), Format::WlaDx);
+    assert_eq!(detect_format(simple), Format::Simple);
+
+    let mut table = SymbolTable::new();
+    for text in &[ca65, vice, wla, simple] {
+        table.merge(&SymbolTable::parse(text).unwrap());
+    }
+    assert_eq!(table.len(), 6);
+    assert_eq!(table.address_of("reset"), Some(0x8000));
+    assert_eq!(table.address_of("ext"), None);
+    assert_eq!(table.address_of("ONE"), None);
+    assert_eq!(table.name_at(0xfffa), Some("vectors"));
+    assert_eq!(table.resolve("irq+2"), Some(0x8008));
+    assert_eq!(table.resolve("count"), Some(0x20));
+    assert_eq!(table.describe(0x8004), Some("nmi+1".to_string()));
+    assert!(SymbolTable::parse("broken line").is_err());
+}
+
+#[test]
+fn code_data_logger_separates_code_from_data() {
+    let program = assemble("
+        .org $0200
+        LDX #$01
+        LDA table,X
+        STA $10,X
+halt:   BNE halt
+table:  .byte 1, 2
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.set_logger(Some(CodeDataLogger::new()));
+    cpu.run(20).unwrap();
+
+    let logger = cpu.logger().unwrap();
+    assert_eq!(logger.usage(0x200), Usage::OPCODE);
+    assert_eq!(logger.usage(0x201), Usage::OPERAND);
+    assert_eq!(logger.usage(0x204), Usage::OPERAND);
+    assert_eq!(logger.usage(0x209), Usage::NONE);
+    assert_eq!(logger.usage(0x20a), Usage::READ);
+    assert_eq!(logger.usage(0x11), Usage::WRITE);
+    // STA $10,X reads $10 before adding X, which must not count as data
+    assert_eq!(logger.usage(0x10), Usage::NONE);
+    assert_eq!(logger.unused_ranges(0x200..0x20b), vec![0x209..0x20a]);
+}
+
+#[test]
+fn profiler_attributes_cycles_to_call_chains() {
+    let program = assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        JSR outer
+        JSR inner
+halt:   BNE halt
+outer:  LDY #$04
+loop:   DEY
+        BNE loop
+        JSR inner
+        RTS
+inner:  RTS
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.set_profiler(Some(Profiler::new()));
+    while cpu.registers.pc != program.symbol("halt").unwrap() as usize {
+        cpu.step().unwrap();
+    }
+
+    let symbols = SymbolTable::from(&program);
+    let profiler = cpu.profiler().unwrap();
+    assert_eq!(profiler.total_cycles(), cpu.cycles());
+    let routines = profiler.routines();
+    let names: Vec<(&str, u64)> = routines.iter()
+        .map(|r| (symbols.name_at(r.address).unwrap(), r.calls))
+        .collect();
+    assert_eq!(names, vec![("main", 0), ("outer", 1), ("inner", 2)]);
+    assert_eq!(routines[0].total_cycles, cpu.cycles());
+    // inner runs once on its own and once on behalf of outer
+    assert_eq!(routines[1].total_cycles, routines[1].self_cycles + routines[2].total_cycles / 2);
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded, Some(&symbols)).unwrap();
+    let stacks: Vec<&str> = ::std::str::from_utf8(&folded).unwrap().lines()
+        .map(|l| l.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(stacks, vec!["main", "main;inner", "main;outer", "main;outer;inner"]);
+}
+
+#[test]
+fn profiler_unwinds_with_the_stack_pointer() {
+    let program = assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        LDY #$10
+loop:   JSR drop
+next:   DEY
+        BNE loop
+halt:   JMP halt
+drop:   PLA             ; throw the return address away
+        PLA
+        JMP next
+irq:    RTI
+        .org $fffe
+        .word irq
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.set_profiler(Some(Profiler::new()));
+    // the interrupt comes before any instruction was profiled
+    cpu.set_irq(true);
+    cpu.step().unwrap();
+    cpu.set_irq(false);
+    while cpu.registers.pc != program.symbol("halt").unwrap() as usize {
+        cpu.step().unwrap();
+    }
+
+    let symbols = SymbolTable::from(&program);
+    let profiler = cpu.profiler().unwrap();
+    assert_eq!(profiler.total_cycles(), cpu.cycles());
+    let calls: Vec<(&str, u64)> = profiler.routines().iter()
+        .map(|r| (symbols.name_at(r.address).unwrap(), r.calls))
+        .collect();
+    assert_eq!(calls, vec![("main", 0), ("drop", 16), ("irq", 1)]);
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded, Some(&symbols)).unwrap();
+    let stacks: Vec<&str> = ::std::str::from_utf8(&folded).unwrap().lines()
+        .map(|l| l.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(stacks, vec!["main", "main;drop", "main;irq"]);
+}
+
+#[test]
+fn shadow_call_stack_follows_calls_and_interrupts() {
+    let program = assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        JSR outer
+after:  BRK
+        .byte 0
+halt:   BNE halt
+outer:  JSR inner
+        RTS
+inner:  LDX #$fd        ; drop our own return address
+        TXS
+        RTS
+irq:    RTI
+        .org $fffe
+        .word irq
+    ").unwrap();
+    let symbol = |name| program.symbol(name).unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.set_call_stack(Some(CallStack::new()));
+    while cpu.registers.pc != symbol("inner") as usize {
+        cpu.step().unwrap();
+    }
+    let sites: Vec<u16> = cpu.call_stack().unwrap().frames().iter().map(|f| f.call_site).collect();
+    assert_eq!(sites, vec![symbol("main") + 3, symbol("outer")]);
+
+    while cpu.registers.pc != symbol("irq") as usize {
+        cpu.step().unwrap();
+    }
+    // inner returned straight to main, unwinding both frames
+    assert_eq!(cpu.call_stack().unwrap().mismatches(), &[Mismatch {
+        pc: symbol("inner") + 3,
+        expected: Some(symbol("outer") + 3),
+        actual: symbol("after")
+    }]);
+    let frame = cpu.call_stack().unwrap().frames()[0];
+    assert_eq!((frame.kind, frame.call_site, frame.return_address),
+               (FrameKind::Interrupt, symbol("after"), symbol("halt")));
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.pc, symbol("halt") as usize);
+    assert_eq!(cpu.call_stack().unwrap().depth(), 0);
+    assert_eq!(cpu.call_stack().unwrap().mismatches().len(), 1);
+}
+
+#[test]
+fn debugger_steps_and_stops_on_breakpoints_and_watchpoints() {
+    let program = assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        JSR outer
+after:  LDA $10
+        STA $11
+halt:   JMP halt
+outer:  JSR inner
+back:   NOP
+        RTS
+inner:  NOP
+        RTS
+    ").unwrap();
+    let symbol = |name| program.symbol(name).unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.poke(0x10, 0x42);
+    cpu.debugger().add_breakpoint(symbol("main"));
+    cpu.debugger().add_breakpoint(symbol("outer"));
+
+    // a breakpoint where the run starts does not stop it again
+    assert_eq!(cpu.run(1000).unwrap(), StopReason::Breakpoint(symbol("outer")));
+    assert_eq!(cpu.cycles(), 10);
+
+    // stepping over a call still stops at breakpoints inside it
+    cpu.debugger().add_breakpoint(symbol("inner"));
+    assert_eq!(cpu.step_over(1000).unwrap(), StopReason::Breakpoint(symbol("inner")));
+    cpu.debugger().remove_breakpoint(symbol("inner"));
+    assert_eq!(cpu.step_out(1000).unwrap(), StopReason::StepComplete);
+    assert_eq!(cpu.registers.pc, symbol("back") as usize);
+    assert_eq!(cpu.step_out(1000).unwrap(), StopReason::StepComplete);
+    assert_eq!(cpu.registers.pc, symbol("after") as usize);
+
+    cpu.set_pc(symbol("outer"));
+    assert_eq!(cpu.step_over(1000).unwrap(), StopReason::StepComplete);
+    assert_eq!((cpu.registers.pc, cpu.registers.s), (symbol("back") as usize, 0xff));
+
+    cpu.set_pc(symbol("after"));
+    cpu.debugger().add_watchpoint(Watchpoint::read(0x10, 0x10));
+    cpu.debugger().add_watchpoint(Watchpoint::write(0x11, 0x12));
+    assert_eq!(cpu.step_over(1000).unwrap(), StopReason::Watchpoint {
+        pc: symbol("after"), address: 0x10, access: Access::Read, value: 0x42
+    });
+    assert_eq!(cpu.step_over(1000).unwrap(), StopReason::Watchpoint {
+        pc: symbol("after") + 2, address: 0x11, access: Access::Write, value: 0x42
+    });
+    // JMP reads neither address, and a plain step over ends after one instruction
+    assert_eq!(cpu.step_over(1000).unwrap(), StopReason::StepComplete);
+    assert_eq!(cpu.registers.pc, symbol("halt") as usize);
+    assert_eq!(cpu.run(100).unwrap(), StopReason::CyclesElapsed);
+}
+
+#[test]
+fn shadow_call_stack_drops_abandoned_frames() {
+    let program = assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        LDY #$03
+loop:   JSR drop
+next:   DEY
+        BNE loop
+        JSR leaf
+        JSR reset
+halt:   JMP halt
+drop:   PLA             ; throw the return address away
+        PLA
+        JMP next
+leaf:   RTS
+reset:  JSR leaf
+        LDX #$ff        ; start over with an empty stack
+        TXS
+        JSR leaf
+        JMP halt
+spin:   BRK
+        .org $fffe
+        .word spin
+    ").unwrap();
+    let symbol = |name| program.symbol(name).unwrap();
+    let mut cpu = CpuBuilder::new().pc(0x200).call_stack(CallStack::new()).build();
+    program.load_into(&mut cpu);
+    while cpu.registers.pc != symbol("leaf") as usize {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.call_stack().unwrap().depth(), 4);
+    // returning from leaf shows that the frames of drop are gone
+    cpu.step().unwrap();
+    assert_eq!(cpu.call_stack().unwrap().depth(), 0);
+
+    while cpu.registers.pc != symbol("halt") as usize {
+        cpu.step().unwrap();
+    }
+    let call_stack = cpu.call_stack().unwrap();
+    assert_eq!(call_stack.depth(), 0);
+    assert!(call_stack.mismatches().is_empty());
+
+    // BRKs that never return fill the stack many times over
+    cpu.set_pc(symbol("spin"));
+    for _ in 0..1000 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.call_stack().unwrap().depth(), 128);
+
+    let mut cpu = Cpu::new(Registers { pc: 0x200, ..Default::default() });
+    program.load_into(&mut cpu);
+    while cpu.registers.pc != symbol("halt") as usize {
+        cpu.step().unwrap();
+    }
+    assert!(cpu.call_stack().is_none());
+}
+
+#[test]
+fn interrupt_lines_and_decimal_mode() {
+    let program = assemble("
+        .org $0200
+        SED
+        CLC
+        LDA #$58
+        ADC #$46        ; 58 + 46 = 104
+        STA $00
+        SEC
+        LDA #$12
+        SBC #$21        ; 12 - 21 = 91, borrow
+        STA $01
+        CLD
+        CLI
+        NOP             ; an IRQ is only taken after the instruction after CLI
+        NOP
+halt:   JMP halt
+irq:    INC $10
+        RTI
+nmi:    INC $11
+        RTI
+        .org $fffa
+        .word nmi, $0200, irq
+    ").unwrap();
+    let symbol = |name| program.symbol(name).unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x200, s: 0xff, p: 0x04.into(), ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.set_irq(true);
+    for _ in 0..12 {
+        cpu.step().unwrap();
+    }
+    assert_eq!((cpu.bus[0x00], cpu.bus[0x01]), (0x04, 0x91));
+    assert_eq!(cpu.pc(), symbol("halt") - 1);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), symbol("irq"));
+    // return address and P without B, which only BRK pushes
+    assert_eq!(&cpu.bus[0x1fd..0x200], &[0xa0, 0x12, 0x02]);
+    cpu.set_irq(false);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.bus[0x10]), (symbol("halt") - 1, 1));
+
+    cpu.set_nmi(true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), symbol("nmi"));
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+    // the line is still active, but NMI only fires on an edge
+    assert_eq!((cpu.pc(), cpu.bus[0x11]), (symbol("halt"), 1));
+    cpu.set_nmi(false);
+    cpu.set_nmi(true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), symbol("nmi"));
+}
+
+#[test]
+fn core_agrees_with_the_reference_interpreter() {
+    if let Err(divergence) = super::fuzz::run(0x6502, 20_000) {
+        panic!("\n{}", divergence);
+    }
+}
+
+#[test]
+fn errors_carry_the_cpu_state_and_recent_history() {
+    let program = assemble("
+        .org $0300
+        LDA #$42
+        LDX #$07
+        .byte $03           ; SLO (zp,X), undocumented
+        .byte $00
+        .byte $12           ; KIL
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x300, s: 0xff, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    let error = cpu.step().unwrap_err();
+    assert_eq!((error.kind, error.pc, error.bank), (ErrorKind::IllegalOpcode(0x03), 0x304, 0));
+    assert_eq!((error.registers.a, error.registers.x, error.registers.s), (0x42, 0x07, 0xff));
+    let history: Vec<u16> = error.history.iter().map(|h| h.0).collect();
+    assert_eq!(history, vec![0x300, 0x302, 0x304]);
+    assert_eq!(error.history[1].1, "LDX #$07");
+    assert!(error.to_string().starts_with("illegal opcode $03 at $0304\n  A:42 X:07"), "{}", error);
+
+    cpu.set_pc(0x306);
+    for _ in 0..2 {
+        let error = cpu.step().unwrap_err();
+        assert_eq!((error.kind, error.pc), (ErrorKind::Halt(0x12), 0x306));
+        assert_eq!(cpu.pc(), 0x306);
+    }
+    assert_eq!(cpu.step().unwrap_err().history.len(), 6);
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn illegal_opcode_policies() {
+    let program = assemble("
+        .org $0400
+start:  LDA #$01
+        .byte $a7, $10      ; LAX $10, undocumented
+        INX
+        .byte $02           ; KIL
+        .org $fffc
+        .word start
+    ").unwrap();
+    let mut cpu = Cpu::new(Registers { pc: 0x400, s: 0xff, ..Default::default() });
+    program.load_into(&mut cpu);
+    cpu.bus[0x10] = 0x42;
+    cpu.step().unwrap();
+    assert_eq!(cpu.step().unwrap_err().kind, ErrorKind::IllegalOpcode(0xa7));
+
+    // skipped as a one byte NOP, so the operand runs as an opcode of its own
+    cpu.set_pc(0x402);
+    cpu.set_illegal_opcode_policy(IllegalOpcodePolicy::Nop);
+    let cycles = cpu.cycles();
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.cycles() - cycles), (0x403, 2));
+
+    cpu.set_pc(0x402);
+    let trace = SharedBuffer::default();
+    cpu.set_tracer(Some(Tracer::new(trace.clone())));
+    cpu.set_illegal_opcode_policy(IllegalOpcodePolicy::Callback(Box::new(|cpu, opcode| {
+        assert_eq!(opcode, 0xa7);
+        let pc = cpu.pc();
+        let value = cpu.bus[cpu.bus[pc as usize] as usize];
+        cpu.registers.a = value;
+        cpu.registers.x = value;
+        cpu.set_pc(pc + 1);
+        Ok(())
+    })));
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.registers.a, cpu.registers.x), (0x404, 0x42, 0x42));
+    let text = String::from_utf8(trace.0.borrow().clone()).unwrap();
+    assert!(text.ends_with("; 0402  illegal opcode A7 emulated by callback\n"), "{}", text);
+
+    cpu.set_illegal_opcode_policy(IllegalOpcodePolicy::Halt);
+    assert_eq!(cpu.run(1000).unwrap(), StopReason::Halted(0x405));
+    assert!(cpu.halted());
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), 0x405);
+    assert_eq!(cpu.run(1000).unwrap(), StopReason::Halted(0x405));
+    assert_eq!(cpu.registers.x, 0x43);
+
+    cpu.reset();
+    assert!(!cpu.halted());
+    assert_eq!((cpu.pc(), cpu.registers.s), (0x400, 0xfc));
+}
+
+/// 2K of RAM mirrored through $0000-$1FFF, a status register at $2000
+/// that clears when read, banked ROM at $8000-$FFFF and nothing else.
+struct MirroredBus {
+    ram: [u8; 0x800],
+    status: u8,
+    rom: Vec<u8>,
+    bank: u8
+}
+
+impl MirroredBus {
+    fn new(program: &Program) -> Self {
+        let mut rom = vec![0; 0x8000];
+        for segment in &program.segments {
+            let start = segment.origin as usize - 0x8000;
+            rom[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        MirroredBus { ram: [0; 0x800], status: 0, rom, bank: 0 }
+    }
+}
+
+impl Bus for MirroredBus {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        let value = self.peek(address);
+        if address == 0x2000 {
+            self.status = 0;
+        }
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            0x0000..=0x1fff => self.ram[address as usize & 0x7ff] = value,
+            0x8000..=0xffff => self.bank = value,
+            _ => return false
+        }
+        true
+    }
+
+    fn peek(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x1fff => Some(self.ram[address as usize & 0x7ff]),
+            0x2000 => Some(self.status),
+            0x8000..=0xffff => Some(self.rom[address as usize - 0x8000]),
+            _ => None
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            0x0000..=0x1fff => self.ram[address as usize & 0x7ff] = value,
+            0x2000 => self.status = value,
+            0x8000..=0xffff => self.rom[address as usize - 0x8000] = value,
+            _ => return false
+        }
+        true
+    }
+
+    fn bank(&self, address: u16) -> u8 {
+        if address >= 0x8000 { self.bank } else { 0 }
+    }
+}
+
+#[test]
+fn builder_attaches_a_bus_and_resets_through_it() {
+    let program = assemble("
+        .org $8000
+start:  LDA #$55
+        STA $0900           ; lands in RAM at $0100
+        STA $8000           ; selects bank $55
+        LDA $4000
+        .org $fffc
+        .word start
+    ").unwrap();
+    let mut cpu = CpuBuilder::with_bus(MirroredBus::new(&program)).s(0x10).a(0xaa).reset().build();
+    assert_eq!((cpu.pc(), cpu.registers.s, cpu.registers.a, cpu.cycles()), (0x8000, 0x0d, 0xaa, 7));
+    assert!(cpu.registers.p.irq_disabled);
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!((cpu.bus().ram[0x100], cpu.bus().bank), (0x55, 0x55));
+
+    let error = cpu.step().unwrap_err();
+    assert_eq!((error.kind, error.pc, error.bank), (ErrorKind::UnmappedAccess(0x4000, Access::Read), 0x8008, 0x55));
+    assert_eq!(cpu.pc(), 0x800b);
+
+    let cpu = CpuBuilder::new().load(0x200, &[0xea]).pc(0x200).x(3).y(4).p(0x01).build();
+    assert_eq!((cpu.bus[0x200], cpu.pc(), cpu.registers.x, cpu.registers.y, cpu.registers.p.carry),
+               (0xea, 0x200, 3, 4, true));
+}
+
+#[test]
+fn registers_and_flags_through_the_public_api() {
+    let mut registers = Registers::default();
+    registers.set_pc(0x0300);
+    registers.set_a(0x80);
+    registers.set_s(0xff);
+    registers.p_mut().set_decimal_mode(true);
+    registers.p_mut().set_carry(true);
+    assert_eq!(registers.to_string(), "PC:0300 A:80 X:00 Y:00 S:FF P:nv-bDizC");
+
+    // B and bit 5 are not stored: whatever is pulled, P holds the other six
+    let p = StatusRegister::from_byte(0xff);
+    assert_eq!((p.to_byte(false), p.to_byte(true)), (0xef, 0xff));
+    assert_eq!(p.to_string(), "NV-bDIZC");
+    assert_eq!(StatusRegister::from_byte(0x30), StatusRegister::default());
+
+    let program = assemble("
+        .org $0300
+        PHP
+        BRK
+        .byte 0
+        .org $0400
+irq:    RTI
+        .org $fffe
+        .word irq
+    ").unwrap();
+    let mut cpu = Cpu::new(registers);
+    program.load_into(&mut cpu);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    // PHP and BRK push B set, an interrupt pushes it clear
+    assert_eq!(cpu.bus[0x1ff], 0x39);
+    assert_eq!(cpu.bus[0x1fc], 0x39);
+    assert!(cpu.registers().p().irq_disabled());
+    cpu.registers_mut().p_mut().set_irq_disabled(false);
+    cpu.set_irq(true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.bus[0x1f9], 0x29);
+    assert_eq!((cpu.registers().pc(), cpu.registers().s()), (0x400, 0xf8));
+}
+
+#[test]
+fn peek_and_poke_leave_devices_timing_and_open_bus_alone() {
+    let program = assemble("
+        .org $8000
+        LDA $2000
+        LDA $4000           ; unmapped, reads the open bus
+    ").unwrap();
+    let mut cpu = CpuBuilder::with_bus(MirroredBus::new(&program)).pc(0x8000).build();
+    cpu.bus_mut().status = 0x81;
+    assert_eq!((cpu.peek(0x2000), cpu.peek(0x2000)), (0x81, 0x81));
+    assert!(cpu.poke(0x0801, 0x12));
+    assert!(cpu.poke(0xfffe, 0x34));
+    assert!(!cpu.poke(0x4000, 0x56));
+    assert_eq!((cpu.peek(0x0001), cpu.bus().bank, cpu.bus().rom[0x7ffe]), (0x12, 0, 0x34));
+    assert_eq!((cpu.cycles(), cpu.open_bus()), (0, 0));
+    assert_eq!(cpu.peek(0x4000), 0);
+
+    cpu.step().unwrap();
+    assert_eq!((cpu.registers().a(), cpu.bus().status, cpu.open_bus()), (0x81, 0, 0x81));
+    let error = cpu.step().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::UnmappedAccess(0x4000, Access::Read));
+    // the last byte on the bus was the high byte of the operand
+    assert_eq!((cpu.registers().a(), cpu.open_bus(), cpu.peek(0x4000)), (0x40, 0x40, 0x40));
+}
+
+#[test]
+fn memory_supplied_by_the_caller() {
+    let program = assemble("
+        .org $0200
+        LDA #$42
+        STA $07ff
+        LDA $0800           ; past the end of 2K
+        .org $fffc
+        .word $0200
+    ").unwrap();
+    let mut memory = [0; 0x10000];
+    for segment in &program.segments {
+        let start = segment.origin as usize;
+        memory[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+    }
+    let mut cpu = CpuBuilder::with_bus(Memory(memory)).reset().build();
+    cpu.run(6).unwrap();
+    assert_eq!((cpu.pc(), cpu.peek(0x07ff)), (0x0205, 0x42));
+
+    let ram: &'static mut [u8] = Box::leak(vec![0; 0x800].into_boxed_slice());
+    let mut cpu = Cpu::with_bus(Memory(ram));
+    program.load_into(&mut cpu);
+    cpu.set_pc(0x0200);
+    cpu.run(6).unwrap();
+    assert_eq!((cpu.bus().0[0x07ff], cpu.poke(0xfffc, 0)), (0x42, false));
+    let error = cpu.step().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::UnmappedAccess(0x0800, Access::Read));
+}
+
+#[test]
+fn trace_lines_follow_the_nestest_layout() {
+    let mut cpu = Cpu::new(Registers { pc: 0xc000, s: 0xfd, p: 0x24.into(), ..Default::default() });
+    cpu.cycles = 7;
+    cpu.fill_memory(0xc000, &[0x4c, 0xf5, 0xc5]);
+    // the first line of nestest.log, without the PPU column
+    assert_eq!(format_line(&cpu),
+               "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+
+    // indexing across a page shows the address actually accessed
+    cpu.registers.pc = 0x0200;
+    cpu.registers.y = 0x20;
+    cpu.fill_memory(0x0200, &[0xb9, 0xf0, 0x12, 0xb1, 0x80, 0x02]);
+    cpu.fill_memory(0x0080, &[0xf0, 0x12]);
+    cpu.fill_memory(0x1310, &[0x55]);
+    assert_eq!(format_line(&cpu),
+               "0200  B9 F0 12  LDA $12F0,Y @ 1310 = 55         A:00 X:00 Y:20 P:24 SP:FD CYC:7");
+    cpu.registers.pc = 0x0203;
+    assert_eq!(format_line(&cpu),
+               "0203  B1 80     LDA ($80),Y = 12F0 @ 1310 = 55  A:00 X:00 Y:20 P:24 SP:FD CYC:7");
+    // undocumented opcodes are marked like nestest marks them
+    cpu.registers.pc = 0x0205;
+    assert_eq!(format_line(&cpu),
+               "0205  02       *.byte $02                       A:00 X:00 Y:20 P:24 SP:FD CYC:7");
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert("init", 0x8000);
+    symbols.insert("counter", 0x0010);
+    cpu.registers.pc = 0x0300;
+    cpu.fill_memory(0x0300, &[0x20, 0x00, 0x80, 0x85, 0x10]);
+    cpu.fill_memory(0x0010, &[0x2a]);
+    assert_eq!(format_line_with_symbols(&cpu, Some(&symbols)),
+               "0300  20 00 80  JSR init                        A:00 X:00 Y:20 P:24 SP:FD CYC:7");
+    cpu.registers.pc = 0x0303;
+    assert_eq!(format_line_with_symbols(&cpu, Some(&symbols)),
+               "0303  85 10     STA counter = 2A                A:00 X:00 Y:20 P:24 SP:FD CYC:7");
+}
+
+#[test]
+fn handler_table_matches_decode() {
+    // each entry runs on its own, right after the fetch of its opcode, and
+    // has to do what the reference interpreter does for that opcode
+    let handlers = Variant::Nmos6502.handlers::<Ram>();
+    let mut rng = Rng::new(0x7ab1e);
+    let mut reference = Reference::new();
+    for opcode in 0..=255u8 {
+        let documented = decode(opcode).is_some();
+        assert_eq!(Variant::Nmos6502.is_documented(opcode), documented);
+        assert_eq!(reference.is_documented(opcode), documented);
+        for _ in 0..16 {
+            let mut cpu = Cpu::default();
+            rng.fill(&mut cpu.bus);
+            let pc = rng.next_u64() as u16;
+            cpu.bus[pc as usize] = opcode;
+            let initial = State { pc, s: rng.byte(), a: rng.byte(), x: rng.byte(), y: rng.byte(), p: rng.byte() | 0x30 };
+            reference.memory.copy_from_slice(&cpu.bus);
+            reference.state = initial;
+            reference.cycles = 0;
+            reference.step();
+
+            cpu.registers = Registers {
+                p: initial.p.into(),
+                pc: pc.wrapping_add(1) as usize,
+                a: initial.a,
+                x: initial.x,
+                y: initial.y,
+                s: initial.s as usize
+            };
+            cpu.instruction_pc = pc as usize;
+            cpu.opcode = opcode;
+            cpu.t_state = 1;
+            cpu.cycles = 1;
+            let result = handlers[opcode as usize](&mut cpu, true);
+            if !documented {
+                assert_eq!(result, Err(ErrorKind::Unimplemented(opcode)));
+                continue;
+            }
+            assert_eq!(result, Ok(true), "{:02X}", opcode);
+            let r = &cpu.registers;
+            let state = State { pc: r.pc as u16, s: r.s as u8, a: r.a, x: r.x, y: r.y, p: r.p.to_byte(true) };
+            assert_eq!((state, cpu.cycles), (reference.state, reference.cycles), "{:02X} from {}", opcode, initial);
+            assert!(cpu.bus[..] == reference.memory[..], "{:02X} from {}", opcode, initial);
+        }
+    }
+}
+
+#[test]
+fn opcode_table_matches_the_cycles_taken() {
+    // every operand points at $1210, directly or through $10, so that
+    // indexing by $FF crosses a page
+    for (opcode, info) in OPCODES.iter().enumerate().filter_map(|(o, i)| i.map(|i| (o as u8, i))) {
+        let mode = info.instruction.operand_mode();
+        for &(pc, index, p) in &[(0x0200, 0x00, 0x00), (0x0200, 0xff, 0xff), (0x02f0, 0x00, 0xff), (0x02f0, 0xff, 0x00)] {
+            let mut cpu = Cpu::new(Registers { pc, x: index, y: index, s: 0xff, p: p.into(), ..Default::default() });
+            cpu.fill_memory(pc, &[opcode, 0x10, 0x12]);
+            cpu.fill_memory(0x10, &[0x10, 0x12]);
+            cpu.step().unwrap();
+            let crossed = index == 0xff && matches!(mode,
+                OperandMode::AbsoluteX | OperandMode::AbsoluteY | OperandMode::IndirectIndexed);
+            let mut expected = info.cycles as u64;
+            if info.page_cross_penalty && crossed {
+                expected += 1;
+            }
+            if info.branch_penalty && cpu.pc() as usize != pc + 2 {
+                // from $0200 the target stays in the page, from $02F0 it does not
+                expected += if pc == 0x02f0 { 2 } else { 1 };
+            }
+            assert_eq!(cpu.cycles(), expected, "{:02X} {} with index {:02X}, P {:02X} at {:04X}",
+                       opcode, info.instruction.mnemonic(), index, p, pc);
+        }
+    }
+}
+
+#[test]
+fn ticks_run_one_bus_cycle_at_a_time() {
+    let program = assemble("
+        .org $8000
+        LDA $2000           ; reads the status register in its fourth cycle
+        STA $10
+        INC $11
+        .org $9000
+irq:    NOP
+        .org $fffe
+        .word irq
+    ").unwrap();
+    let mut cpu = CpuBuilder::with_bus(MirroredBus::new(&program)).pc(0x8000).s(0xff).build();
+    // another chip counts the cycles into the status register
+    for cycle in 0..4 {
+        assert_eq!(cpu.at_instruction_boundary(), cycle == 0);
+        cpu.bus_mut().status = cycle;
+        cpu.tick().unwrap();
+    }
+    assert_eq!((cpu.registers().a(), cpu.pc(), cpu.cycles()), (3, 0x8003, 4));
+    assert!(cpu.at_instruction_boundary());
+
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.bus().ram[0x10], 0);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.bus().ram[0x10], 3);
+
+    // INC writes the unmodified value back before the result, and an IRQ
+    // raised in between waits for the instruction to complete
+    cpu.bus_mut().ram[0x11] = 0x41;
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+    }
+    cpu.set_irq(true);
+    cpu.tick().unwrap();
+    assert_eq!((cpu.bus().ram[0x11], cpu.at_instruction_boundary()), (0x41, false));
+    cpu.tick().unwrap();
+    assert_eq!((cpu.bus().ram[0x11], cpu.pc(), cpu.at_instruction_boundary()), (0x42, 0x8007, true));
+
+    // step completes an interrupt sequence that ticks have started
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+    }
+    assert_eq!((cpu.pc(), cpu.registers().s(), cpu.bus().ram[0x1ff]), (0x8007, 0xfe, 0x80));
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.registers().s(), cpu.cycles()), (0x9000, 0xfc, 19));
+    assert_eq!(&cpu.bus().ram[0x1fd..0x200], &[0x20, 0x07, 0x80]);
+}
+
+/// A chip that counts master clock cycles in fours and pulls NMI at a set
+/// time, and how often it was asked to catch up.
+struct Counter {
+    timestamp: u64,
+    nmi_at: u64,
+    runs: usize
+}
+
+impl Component for Counter {
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn run_to(&mut self, timestamp: u64) {
+        if timestamp > self.timestamp {
+            self.timestamp = timestamp;
+            self.runs += 1;
+        }
+    }
+}
+
+/// ROM and RAM at eight master clock cycles an access, and the counter's
+/// register at six.
+struct ClockedBus {
+    ram: MirroredBus,
+    counter: Counter,
+    now: u64
+}
+
+impl Bus for ClockedBus {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        if address == 0x2000 {
+            self.counter.run_to(self.now);
+            return Some((self.counter.timestamp / 4) as u8);
+        }
+        self.ram.read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        self.ram.write(address, value)
+    }
+
+    fn peek(&self, address: u16) -> Option<u8> {
+        self.ram.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) -> bool {
+        self.ram.poke(address, value)
+    }
+
+    fn access_time(&self, address: u16) -> u64 {
+        if address == 0x2000 { 6 } else { 8 }
+    }
+
+    fn synchronize(&mut self, timestamp: u64) {
+        self.now = timestamp;
+    }
+}
+
+impl Machine for ClockedBus {
+    fn for_each_component(&mut self, f: &mut dyn FnMut(&mut dyn Component)) {
+        f(&mut self.counter);
+    }
+
+    fn next_event(&self) -> u64 {
+        if self.nmi() { u64::MAX } else { self.counter.nmi_at }
+    }
+
+    fn nmi(&self) -> bool {
+        self.counter.timestamp >= self.counter.nmi_at
+    }
+}
+
+#[test]
+fn scheduler_catches_chips_up_lazily() {
+    let program = assemble("
+        .org $8000
+        LDA $2000
+        STA $00
+loop:   JMP loop
+nmi:    LDA $2000
+        STA $01
+done:   JMP done
+        .org $fffa
+        .word nmi
+    ").unwrap();
+    let counter = Counter { timestamp: 0, nmi_at: 100, runs: 0 };
+    let bus = ClockedBus { ram: MirroredBus::new(&program), counter, now: 0 };
+    let cpu = CpuBuilder::with_bus(bus).pc(0x8000).s(0xff).build();
+    let mut snes = Scheduler::new(cpu, Region::Ntsc);
+
+    // three fetches from ROM, then the counter is read at 24
+    snes.run_for(30).unwrap();
+    assert_eq!((snes.timestamp(), snes.cpu().registers().a()), (30, 6));
+    assert_eq!((snes.cpu().bus().counter.timestamp, snes.cpu().bus().counter.runs), (30, 2));
+
+    // the NMI is noticed after the cycle that reaches 100, at the end of
+    // the JMP, and the run stops in the middle of the interrupt sequence
+    snes.run_for(100).unwrap();
+    assert_eq!(snes.timestamp(), 134);
+    assert!(!snes.cpu().at_instruction_boundary());
+
+    // the handler reads the counter 7 cycles later and 3 fetches in
+    snes.run_for(60).unwrap();
+    assert_eq!((snes.timestamp(), snes.cpu().registers().a()), (196, 182 / 4));
+    assert_eq!((snes.cpu().bus().counter.timestamp, snes.cpu().bus().counter.runs), (196, 6));
+
+    assert_eq!(Region::Ntsc.clocks(Duration::from_secs(1)), 21_477_272);
+    assert_eq!(Region::Pal.clocks(Duration::from_millis(1)), 21_281);
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn c_api_runs_a_program_through_callbacks() {
+    use std::ffi::CStr;
+    use std::os::raw::c_void;
+    use super::capi::*;
+
+    extern "C" fn read(user: *mut c_void, address: u16) -> u8 {
+        unsafe { *(user as *const u8).add(address as usize) }
+    }
+    extern "C" fn write(user: *mut c_void, address: u16, value: u8) {
+        unsafe { *(user as *mut u8).add(address as usize) = value }
+    }
+
+    let program = assemble("
+        .org $0200
+start:  LDA #$42
+        STA $10
+        CLI
+loop:   JMP loop
+irq:    INC $11
+        RTI
+        .org $fffc
+        .word start, irq
+    ").unwrap();
+    let mut memory = vec![0u8; 0x10000];
+    for segment in &program.segments {
+        let start = segment.origin as usize;
+        memory[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+    }
+    let mut callbacks = Callbacks {
+        user: memory.as_mut_ptr() as *mut c_void,
+        read: Some(read),
+        write: None,
+        peek: Some(read)
+    };
+    assert!(sfox_cpu_new(&callbacks).is_none());
+    callbacks.write = Some(write);
+    let mut cpu = sfox_cpu_new(&callbacks).unwrap();
+    sfox_cpu_reset(&mut cpu);
+    assert_eq!(sfox_cpu_run(&mut cpu, 20), SFOX_OK);
+    sfox_cpu_set_irq(&mut cpu, true);
+    assert_eq!(sfox_cpu_step(&mut cpu), SFOX_OK);
+    sfox_cpu_set_irq(&mut cpu, false);
+    assert_eq!(sfox_cpu_run(&mut cpu, 20), SFOX_OK);
+    assert!(sfox_cpu_error(&cpu).is_null());
+
+    let mut registers = RegisterFile::default();
+    sfox_cpu_get_registers(&cpu, &mut registers);
+    assert_eq!(registers, RegisterFile { pc: 0x0205, a: 0x42, x: 0, y: 0, s: 0xfd, p: 0x20 });
+    assert_eq!((memory[0x10], memory[0x11], sfox_cpu_cycles(&cpu)), (0x42, 1, 56));
+
+    memory[0x0300] = 0xff;
+    registers.pc = 0x0300;
+    registers.p = 0xff;
+    sfox_cpu_set_registers(&mut cpu, &registers);
+    assert_eq!(sfox_cpu_step(&mut cpu), SFOX_ILLEGAL_OPCODE);
+    let error = unsafe { CStr::from_ptr(sfox_cpu_error(&cpu)) }.to_str().unwrap();
+    assert!(error.starts_with("illegal opcode $FF at $0300\n  A:42 X:00 Y:00 P:EF SP:FD"), "{}", error);
+    sfox_cpu_free(Some(cpu));
+    sfox_cpu_free(None);
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn c_header_is_generated_by_cbindgen() {
+    // build.rs generates the header from capi.rs on every capi build
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/sfoxidized.h"));
+    let header = include_str!("../../../include/sfoxidized.h");
+    assert!(header == generated, "include/sfoxidized.h is out of date, copy {}/sfoxidized.h over it",
+            env!("OUT_DIR"));
+}
@@ -0,0 +1,228 @@
+//! The monitor, driven by scripts the way a user would type at it.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use super::super::assembler::{assemble, Program};
+use super::super::monitor::Monitor;
+use super::super::symbols::SymbolTable;
+
+fn run(monitor: &mut Monitor, script: &str) -> String {
+    let mut out = Vec::new();
+    monitor.run_script(script.as_bytes(), &mut out, false).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("sfoxidized-monitor-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory.join(name)
+}
+
+fn program() -> Program {
+    assemble("
+        .org $0200
+main:   LDX #$ff
+        TXS
+        JSR outer
+        STA $10
+halt:   JMP halt
+outer:  JSR inner
+        RTS
+inner:  LDA #$42
+        RTS
+        .org $fffc
+        .word main
+    ").unwrap()
+}
+
+fn monitor_with_program() -> Monitor {
+    let program = program();
+    let mut monitor = Monitor::default();
+    program.load_into(monitor.cpu());
+    monitor.symbols().merge(&SymbolTable::from(&program));
+    monitor
+}
+
+#[test]
+fn memory_commands() {
+    let mut monitor = monitor_with_program();
+    let binary = scratch("data.bin");
+    fs::write(&binary, [0xde, 0xad]).unwrap();
+    let output = run(&mut monitor, &format!("
+        e 10 01 02 03
+        f 20 2f 41
+        m 10 12
+        m 20 2f
+        a  300  LDA   #$42      ; any amount of space between the words
+        a 302 STA ($10),Y
+        d 300 2
+        l {} 310
+        m 310 311
+        e 10 100
+        f 30 20 00
+        a 304 JSR nowhere
+        a 300
+        zz
+    ", binary.display()));
+    assert_eq!(output, "\
+0010  01 02 03                                         ...
+0020  41 41 41 41 41 41 41 41 41 41 41 41 41 41 41 41  AAAAAAAAAAAAAAAA
+0300  A9 42     LDA #$42
+0302  91 10     STA ($10),Y
+0310  DE AD                                            ..
+?byte out of range: 100
+?end lies before start
+?line 2: undefined symbol nowhere
+?usage: a <addr> <instruction>
+?unknown command: zz
+");
+
+    // without an address, d and m carry on where they stopped
+    let output = run(&mut monitor, "d\nm");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 16 + 8);
+    assert_eq!(lines[0], "0304  00        BRK");
+    assert!(lines[16].starts_with("0312  00 00 "));
+}
+
+#[test]
+fn execution_commands() {
+    let mut monitor = monitor_with_program();
+    monitor.cpu().poke(0x300, 0x02);
+    let output = run(&mut monitor, "
+        r pc=main a=1 p=24
+        b inner
+        b
+        g
+        bt
+        o
+        o
+        bd inner
+        bd inner
+        r pc=main
+        s 3
+        n
+        w 10
+        wd 10 r
+        g
+        wd 10
+        g halt 20
+        reset
+        ill halt
+        g 300
+        ill sometimes
+        r q=1
+    ");
+    assert_eq!(output, "\
+0200  A2 FF     LDX #$FF                        A:01 X:00 Y:00 P:24 SP:00 CYC:0
+020F  inner
+breakpoint at 020F (inner)
+#0  020F inner
+#1  020B outer
+#2  0203 main+3
+020F  A9 42     LDA #$42                        A:01 X:FF Y:00 P:A4 SP:FB CYC:16
+#0  020F inner
+#1  020B outer
+#2  0203 main+3
+020E  60        RTS                             A:42 X:FF Y:00 P:24 SP:FD CYC:24
+0206  85 10     STA $10 = 00                    A:42 X:FF Y:00 P:24 SP:FF CYC:30
+?no breakpoint at 020F
+0200  A2 FF     LDX #$FF                        A:42 X:FF Y:00 P:24 SP:FF CYC:30
+0200  A2 FF     LDX #$FF                        A:42 X:FF Y:00 P:24 SP:FF CYC:30
+0202  9A        TXS                             A:42 X:FF Y:00 P:A4 SP:FF CYC:32
+0203  20 0B 02  JSR outer                       A:42 X:FF Y:00 P:A4 SP:FF CYC:34
+020E  60        RTS                             A:42 X:FF Y:00 P:24 SP:FD CYC:54
+?no such watchpoint
+Write of 42 at 0010 by instruction at 0206
+0208  4C 08 02  JMP halt                        A:42 X:FF Y:00 P:24 SP:FF CYC:63
+cycle budget exhausted
+0208  4C 08 02  JMP halt                        A:42 X:FF Y:00 P:24 SP:FF CYC:96
+0200  A2 FF     LDX #$FF                        A:42 X:FF Y:00 P:24 SP:FC CYC:103
+halted by illegal opcode at 0300
+0300  02       *.byte $02                       A:42 X:FF Y:00 P:24 SP:FC CYC:104
+?usage: ill fail|nop|halt
+?unknown register: q
+");
+}
+
+#[test]
+fn tool_commands() {
+    let symbols = scratch("labels.txt");
+    fs::write(&symbols, "main = $0200\n").unwrap();
+    let broken = scratch("broken.txt");
+    fs::write(&broken, "al 00xyz .main\n").unwrap();
+    let trace = scratch("trace.log");
+    let cdl = scratch("code.cdl");
+    let folded = scratch("folded.txt");
+    let report = scratch("report.txt");
+    let mut monitor = Monitor::default();
+    program().load_into(monitor.cpu());
+    let output = run(&mut monitor, &format!("
+        sym
+        sym {}
+        sym {}
+        d main 1
+        t {}
+        cdl
+        cdl on
+        p on
+        r pc=main
+        s 3
+        cdl 200 20f
+        cdl save {}
+        cdl clear
+        cdl 200 20f
+        cdl load {}
+        cdl 200 20f
+        p folded {}
+        p {}
+        p clear
+        p off
+        p
+        cdl off
+        cdl
+        t off
+        q
+        r
+    ", symbols.display(), broken.display(), trace.display(), cdl.display(), cdl.display(),
+       folded.display(), report.display()));
+    assert_eq!(output, "\
+0 symbols
+1 symbols
+?line 1: cannot read a symbol from `al 00xyz .main`
+main:
+0200  A2 FF     LDX #$FF
+?code/data logging is off
+0200  A2 FF     LDX #$FF                        A:00 X:00 Y:00 P:20 SP:00 CYC:0
+0200  A2 FF     LDX #$FF                        A:00 X:00 Y:00 P:20 SP:00 CYC:0
+0202  9A        TXS                             A:00 X:FF Y:00 P:A0 SP:00 CYC:2
+0203  20 0B 02  JSR $020B                       A:00 X:FF Y:00 P:A0 SP:FF CYC:4
+16 bytes: 6 code, 0 data, 10 never accessed
+0206-020F
+16 bytes: 0 code, 0 data, 16 never accessed
+0200-020F
+16 bytes: 6 code, 0 data, 10 never accessed
+0206-020F
+?profiling is off
+?code/data logging is off
+");
+    assert!(monitor.finished());
+
+    assert_eq!(fs::read_to_string(&trace).unwrap(), "\
+0200  A2 FF     LDX #$FF                        A:00 X:00 Y:00 P:20 SP:00 CYC:0
+0202  9A        TXS                             A:00 X:FF Y:00 P:A0 SP:00 CYC:2
+0203  20 0B 02  JSR $020B                       A:00 X:FF Y:00 P:A0 SP:FF CYC:4
+");
+    assert_eq!(fs::read_to_string(&folded).unwrap(), "main 10\n");
+    let report = fs::read_to_string(&report).unwrap();
+    assert!(report.starts_with("10 cycles profiled\n"));
+    assert!(report.contains("\n$020B                           1            0   0.00            0   0.00\n"));
+
+    let mut help = Vec::new();
+    monitor.execute("h", &mut help).unwrap();
+    assert_eq!(String::from_utf8(help).unwrap().lines().count(), 27);
+    assert_eq!(monitor.execute("sym nothing.txt", &mut Vec::new()),
+               Err("nothing.txt: No such file or directory (os error 2)".to_string()));
+}
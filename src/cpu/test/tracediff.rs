@@ -0,0 +1,83 @@
+//! Trace comparison as the `tracediff` binary does it.
+
+use std::io::Cursor;
+use super::SharedBuffer;
+use super::super::trace::Tracer;
+use super::super::tracediff::{compare, parse_entry, parse_log, Entry, Options, Outcome};
+
+fn log(text: &str) -> Vec<Entry> {
+    parse_log(Cursor::new(text)).unwrap()
+}
+
+#[test]
+fn fields_are_found_in_different_formats() {
+    let nestest = parse_entry(1, "C000  4C F5 C5  JMP $C5F5    A:00 X:01 Y:02 P:24 SP:FD PPU:  0, 21 CYC:7").unwrap();
+    assert_eq!((nestest.pc, nestest.a, nestest.x, nestest.y), (0xc000, Some(0x00), Some(0x01), Some(0x02)));
+    assert_eq!((nestest.p, nestest.s, nestest.cycles), (Some(0x24), Some(0xfd), Some(7)));
+
+    // a bank prefix, lower case, S instead of SP, and no P
+    let other = parse_entry(2, "00:8000 lda #$00  a:10 x:20 y:30 s:ff cycle:12").unwrap();
+    assert_eq!((other.pc, other.a, other.s, other.p, other.cycles), (0x8000, Some(0x10), Some(0xff), None, Some(12)));
+    assert_eq!(parse_entry(3, "$8000: NOP").map(|e| e.pc), Some(0x8000));
+
+    assert_eq!(parse_entry(4, "Trace started"), None);
+    assert_eq!(parse_entry(5, ""), None);
+    assert_eq!(log("header\nC000  NOP  A:00\n\nC001  NOP  A:00\n").iter().map(|e| e.number).collect::<Vec<_>>(),
+               vec![2, 4]);
+}
+
+#[test]
+fn b_and_unused_bits_of_p_only_count_when_strict() {
+    let ours = log("C000  NOP  P:24\n");
+    let reference = log("C000  NOP  P:34\n");
+    assert_eq!(compare(&Options::default(), &ours, &reference), Outcome::Agree(1));
+    let strict = Options { strict_p: true, ..Options::default() };
+    assert_eq!(compare(&strict, &ours, &reference),
+               Outcome::Diverge { index: 0, differences: vec!["P: ours=24 reference=34".to_string()] });
+    // the flags themselves always count, shown without the ignored bits
+    assert_eq!(compare(&Options::default(), &ours, &log("C000  NOP  P:A5\n")),
+               Outcome::Diverge { index: 0, differences: vec!["P: ours=04 reference=85".to_string()] });
+}
+
+#[test]
+fn cycles_are_relative_to_the_first_line_unless_absolute() {
+    let ours = log("C000  NOP  CYC:7\nC001  NOP  CYC:9\nC002  NOP  CYC:12\n");
+    let reference = log("C000  NOP  CYC:0\nC001  NOP  CYC:2\nC002  NOP  CYC:4\n");
+    assert_eq!(compare(&Options::default(), &ours, &reference), Outcome::Diverge {
+        index: 2,
+        differences: vec!["CYC: ours=5 reference=4 (relative to first line)".to_string()]
+    });
+    let absolute = Options { absolute_cycles: true, ..Options::default() };
+    assert_eq!(compare(&absolute, &ours, &reference),
+               Outcome::Diverge { index: 0, differences: vec!["CYC: ours=7 reference=0".to_string()] });
+    let ignore = Options { ignore_cycles: true, ..Options::default() };
+    assert_eq!(compare(&ignore, &ours, &reference), Outcome::Agree(3));
+}
+
+#[test]
+fn a_log_that_ends_early_is_reported() {
+    let long = log("C000  NOP  A:00\nC001  NOP  A:00\nC002  NOP  A:00\n");
+    let short = log("C000  NOP  A:00\nC001  NOP  A:00\n");
+    assert_eq!(compare(&Options::default(), &short, &long), Outcome::Ends { index: 2, ours_shorter: true });
+    assert_eq!(compare(&Options::default(), &long, &short), Outcome::Ends { index: 2, ours_shorter: false });
+    // a difference before the end is reported first
+    let different = log("C000  NOP  A:01\n");
+    assert_eq!(compare(&Options::default(), &different, &long),
+               Outcome::Diverge { index: 0, differences: vec!["A: ours=01 reference=00".to_string()] });
+}
+
+#[test]
+fn tracer_notes_are_not_instructions() {
+    let note = SharedBuffer::default();
+    Tracer::new(note.clone()).illegal_opcode(0x401, 0xa7, "emulated by callback");
+    let note = String::from_utf8(note.0.borrow().clone()).unwrap();
+    let ours = log(&format!("0400  EA        NOP  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                             0401  A7 10    *LAX $10 = 42  A:00 X:00 Y:00 P:24 SP:FD CYC:9\n\
+                             {}\
+                             0403  EA        NOP  A:42 X:42 Y:00 P:24 SP:FD CYC:12\n", note));
+    let reference = log("0400  EA        NOP  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                         0401  A7 10    *LAX $10 = 42  A:00 X:00 Y:00 P:24 SP:FD CYC:9\n\
+                         0403  EA        NOP  A:42 X:42 Y:00 P:24 SP:FD CYC:12\n");
+    assert_eq!(ours.iter().map(|e| e.number).collect::<Vec<_>>(), vec![1, 2, 4]);
+    assert_eq!(compare(&Options::default(), &ours, &reference), Outcome::Agree(3));
+}
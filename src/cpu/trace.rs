@@ -0,0 +1,101 @@
+//! Instruction trace logging. Lines follow the layout of the widely used
+//! nestest reference log, minus the PPU column, so that traces can be diffed
+//! against known-good logs:
+//!
+//! ```text
+//! C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+//! ```
+
+use std::io::Write;
+use super::{Bus, Cpu};
+use super::disassembler::{disassemble_with_symbols, Line};
+use super::instruction::{Instruction, OperandMode};
+use super::symbols::SymbolTable;
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    symbols: Option<SymbolTable>
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Self {
+        Tracer { out: Box::new(out), symbols: None }
+    }
+
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    pub fn trace<B: Bus>(&mut self, cpu: &Cpu<B>) {
+        let _ = writeln!(self.out, "{}", format_line_with_symbols(cpu, self.symbols.as_ref()));
+    }
+
+    /// Notes how the illegal opcode on the line just traced was dealt with,
+    /// as a `;` comment that trace comparison skips.
+    pub fn illegal_opcode(&mut self, pc: u16, opcode: u8, outcome: &str) {
+        let _ = writeln!(self.out, "; {:04X}  illegal opcode {:02X} {}", pc, opcode, outcome);
+    }
+}
+
+pub fn format_line<B: Bus>(cpu: &Cpu<B>) -> String {
+    format_line_with_symbols(cpu, None)
+}
+
+pub fn format_line_with_symbols<B: Bus>(cpu: &Cpu<B>, symbols: Option<&SymbolTable>) -> String {
+    let peek = |address: u16| cpu.peek(address);
+    let line = disassemble_with_symbols(peek, cpu.registers.pc as u16, symbols);
+    let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let marker = if line.info.is_some() { ' ' } else { '*' };
+    let text = format!("{}{}", line.text, annotation(cpu, &line));
+    // The B flag only exists on the stack, reference logs show P without it
+    let p = cpu.registers.p.to_byte(false);
+    format!("{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            line.address, bytes.join(" "), marker, text,
+            cpu.registers.a, cpu.registers.x, cpu.registers.y, p,
+            cpu.s_into_byte(), cpu.cycles)
+}
+
+fn annotation<B: Bus>(cpu: &Cpu<B>, line: &Line) -> String {
+    let info = match line.info {
+        Some(info) => info,
+        None => return String::new()
+    };
+    let peek = |address: u16| cpu.peek(address);
+    let peek_word = |low: u16, high: u16| peek(low) as u16 | ((peek(high) as u16) << 8);
+    let x = cpu.registers.x;
+    let y = cpu.registers.y;
+    let operand = line.operand();
+    match info.instruction.operand_mode() {
+        OperandMode::ZeroPage | OperandMode::Absolute => match info.instruction {
+            Instruction::Jump(_) | Instruction::Misc(_) => String::new(),
+            _ => format!(" = {:02X}", peek(operand))
+        },
+        OperandMode::ZeroPageX | OperandMode::ZeroPageY => {
+            let index = if info.instruction.operand_mode() == OperandMode::ZeroPageX { x } else { y };
+            let address = (operand as u8).wrapping_add(index) as u16;
+            format!(" @ {:02X} = {:02X}", address, peek(address))
+        },
+        OperandMode::AbsoluteX | OperandMode::AbsoluteY => {
+            let index = if info.instruction.operand_mode() == OperandMode::AbsoluteX { x } else { y };
+            let address = operand.wrapping_add(index as u16);
+            format!(" @ {:04X} = {:02X}", address, peek(address))
+        },
+        OperandMode::IndexedIndirect => {
+            let pointer = (operand as u8).wrapping_add(x);
+            let address = peek_word(pointer as u16, pointer.wrapping_add(1) as u16);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, address, peek(address))
+        },
+        OperandMode::IndirectIndexed => {
+            let pointer = operand as u8;
+            let base = peek_word(pointer as u16, pointer.wrapping_add(1) as u16);
+            let address = base.wrapping_add(y as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, address, peek(address))
+        },
+        OperandMode::Indirect => {
+            let high = (operand & 0xff00) | (operand.wrapping_add(1) & 0x00ff);
+            format!(" = {:04X}", peek_word(operand, high))
+        },
+        _ => String::new()
+    }
+}
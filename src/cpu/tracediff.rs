@@ -0,0 +1,153 @@
+//! Compares two instruction traces and finds the first line where PC,
+//! registers, flags or cycle counts diverge; the `tracediff` binary is a
+//! front end to it.
+//!
+//! Lines are matched up by position after skipping `;` comments and anything
+//! that does not start with a program counter. Fields are located by their `A:`, `X:`,
+//! `Y:`, `P:`, `SP:` (or `S:`) and `CYC:` prefixes, so spacing, case,
+//! disassembly text and extra columns such as PPU timing don't matter.
+//! Fields missing from either log are not compared.
+
+use std::io::{self, BufRead};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Line number in the log, from 1.
+    pub number: usize,
+    pub text: String,
+    pub pc: u16,
+    pub a: Option<u8>,
+    pub x: Option<u8>,
+    pub y: Option<u8>,
+    pub p: Option<u8>,
+    pub s: Option<u8>,
+    pub cycles: Option<u64>
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Also compare the B and unused bits of P.
+    pub strict_p: bool,
+    /// Compare raw cycle counts instead of counts relative to each log's
+    /// first line.
+    pub absolute_cycles: bool,
+    pub ignore_cycles: bool
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Both logs have this many instructions, and they all agree.
+    Agree(usize),
+    /// The instruction at `index` differs in the ways described.
+    Diverge { index: usize, differences: Vec<String> },
+    /// The logs agree for `index` instructions, then one of them ends.
+    Ends { index: usize, ours_shorter: bool }
+}
+
+fn field<'a>(line: &'a str, keys: &[&str]) -> Option<&'a str> {
+    line.split_whitespace().filter_map(|token| {
+        let colon = token.find(':')?;
+        let (key, value) = (&token[..colon], &token[colon + 1..]);
+        if value.is_empty() || !keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            None
+        }
+        else {
+            Some(value)
+        }
+    }).next()
+}
+
+fn hex_field(line: &str, keys: &[&str]) -> Option<u8> {
+    field(line, keys).and_then(|v| u8::from_str_radix(v, 16).ok())
+}
+
+/// Parses line `number` of a log, or returns `None` if it is a `;` comment
+/// or does not start with a program counter.
+pub fn parse_entry(number: usize, text: &str) -> Option<Entry> {
+    if text.trim_start().starts_with(';') {
+        return None;
+    }
+    let first = text.split_whitespace().next()?;
+    let first = first.trim_end_matches(':');
+    let first = first.rsplit(':').next().unwrap_or(first).trim_start_matches('$');
+    if first.len() != 4 {
+        return None;
+    }
+    let pc = u16::from_str_radix(first, 16).ok()?;
+    Some(Entry {
+        number,
+        text: text.to_string(),
+        pc,
+        a: hex_field(text, &["A"]),
+        x: hex_field(text, &["X"]),
+        y: hex_field(text, &["Y"]),
+        p: hex_field(text, &["P"]),
+        s: hex_field(text, &["SP", "S"]),
+        cycles: field(text, &["CYC", "CYCLE", "CYCLES"]).and_then(|v| v.parse().ok())
+    })
+}
+
+pub fn parse_log<R: BufRead>(log: R) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        entries.extend(parse_entry(i + 1, &line?));
+    }
+    Ok(entries)
+}
+
+fn compare_field<T>(differences: &mut Vec<String>, name: &str, ours: Option<T>, reference: Option<T>, width: usize)
+    where T: PartialEq + std::fmt::UpperHex
+{
+    if let (Some(o), Some(r)) = (ours, reference) {
+        if o != r {
+            differences.push(format!("{}: ours={:0w$X} reference={:0w$X}", name, o, r, w = width));
+        }
+    }
+}
+
+/// Describes how two entries differ. `base` holds the cycle counts of the
+/// first line of each log, which relative cycle counts start from.
+pub fn differences(options: &Options, ours: &Entry, reference: &Entry, base: (u64, u64)) -> Vec<String> {
+    let mut differences = Vec::new();
+    if ours.pc != reference.pc {
+        differences.push(format!("PC: ours={:04X} reference={:04X}", ours.pc, reference.pc));
+    }
+    compare_field(&mut differences, "A", ours.a, reference.a, 2);
+    compare_field(&mut differences, "X", ours.x, reference.x, 2);
+    compare_field(&mut differences, "Y", ours.y, reference.y, 2);
+    let mask = if options.strict_p { 0xff } else { 0xcf };
+    compare_field(&mut differences, "P", ours.p.map(|p| p & mask), reference.p.map(|p| p & mask), 2);
+    compare_field(&mut differences, "SP", ours.s, reference.s, 2);
+    if !options.ignore_cycles {
+        if let (Some(o), Some(r)) = (ours.cycles, reference.cycles) {
+            let (o, r) = if options.absolute_cycles {
+                (o, r)
+            }
+            else {
+                (o.wrapping_sub(base.0), r.wrapping_sub(base.1))
+            };
+            if o != r {
+                differences.push(format!("CYC: ours={} reference={}{}", o, r,
+                                         if options.absolute_cycles { "" } else { " (relative to first line)" }));
+            }
+        }
+    }
+    differences
+}
+
+pub fn compare(options: &Options, ours: &[Entry], reference: &[Entry]) -> Outcome {
+    let base = (
+        ours.first().and_then(|e| e.cycles).unwrap_or(0),
+        reference.first().and_then(|e| e.cycles).unwrap_or(0)
+    );
+    for (index, (o, r)) in ours.iter().zip(reference).enumerate() {
+        let differences = differences(options, o, r, base);
+        if !differences.is_empty() {
+            return Outcome::Diverge { index, differences };
+        }
+    }
+    if ours.len() != reference.len() {
+        return Outcome::Ends { index: ours.len().min(reference.len()), ours_shorter: ours.len() < reference.len() };
+    }
+    Outcome::Agree(ours.len())
+}
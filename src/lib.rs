@@ -0,0 +1,40 @@
+//! sfoxidized, an emulator in the making for the SNES, starting with its
+//! 6502 family CPU core.
+//!
+//! The public surface:
+//!
+//! * [`cpu::Cpu`] executes instructions against a [`cpu::Bus`], by default
+//!   64K of [`cpu::Ram`]; [`cpu::CpuBuilder`] sets one up. `step` runs a
+//!   whole instruction, `tick` a single bus cycle of one.
+//! * [`cpu::Registers`] and [`cpu::StatusRegister`] read and change the CPU
+//!   state, [`cpu::Error`] describes why execution stopped.
+//! * [`instruction`] decodes opcodes, [`cpu::disassembler`] and
+//!   [`cpu::assembler`] translate between machine code and assembly.
+//! * Tools built on top: the tracer ([`cpu::trace`]) and trace comparison
+//!   ([`cpu::tracediff`]), breakpoints and watchpoints ([`cpu::debugger`]),
+//!   a machine language monitor ([`cpu::monitor`]), a GDB stub
+//!   ([`cpu::gdb`]), symbol files ([`cpu::symbols`]), the code/data logger
+//!   ([`cpu::cdl`]), the cycle profiler ([`cpu::profiler`]) and the
+//!   differential fuzzer ([`cpu::fuzz`]).
+//! * [`scheduler`] runs the CPU and the chips behind its bus against the
+//!   master clock, [`cpu::Cpu::cycles`] being the machine's timestamp.
+//! * With the `capi` feature, `cpu::capi` exports the core to C and C++,
+//!   declared in `include/sfoxidized.h`.
+//!
+//! The binaries in this package (the monitor, the benchmark, the functional
+//! test runner, the fuzzer and the trace differ) only use this surface.
+//!
+//! Without the default `std` feature the crate is `no_std` and allocates
+//! nothing: the CPU runs against memory the caller owns, such as
+//! [`cpu::bus::Memory`] over a static array, and only the core remains; the
+//! tools above, `Ram` and the instruction history in errors need `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+extern crate core;
+
+pub mod cpu;
+pub mod scheduler;
+
+pub use cpu::instruction;
@@ -1,5 +1,50 @@
-mod sfoxidized;
+extern crate sfoxidized;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+use sfoxidized::cpu::monitor::Monitor;
+
+fn usage() -> ! {
+    eprintln!("usage: sfoxidized [-i] [script]");
+    eprintln!("  Starts a machine language monitor. Commands are read from the script,");
+    eprintln!("  if given, and otherwise from standard input. -i keeps the monitor");
+    eprintln!("  running interactively after the script finishes.");
+    process::exit(2);
+}
 
 fn main() {
-    println!("Hello, world!");
+    let mut interactive = false;
+    let mut script = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-i" => interactive = true,
+            "-h" | "--help" => usage(),
+            _ if script.is_none() && !arg.starts_with('-') => script = Some(arg),
+            _ => usage()
+        }
+    }
+
+    let mut monitor = Monitor::default();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if let Some(path) = script {
+        let file = File::open(&path).unwrap_or_else(|e| {
+            eprintln!("sfoxidized: cannot open {}: {}", path, e);
+            process::exit(1);
+        });
+        if let Err(e) = monitor.run_script(BufReader::new(file), &mut out, false) {
+            eprintln!("sfoxidized: {}", e);
+            process::exit(1);
+        }
+        if !interactive || monitor.finished() {
+            return;
+        }
+    }
+    let stdin = io::stdin();
+    if let Err(e) = monitor.run_script(stdin.lock(), &mut out, true) {
+        eprintln!("sfoxidized: {}", e);
+        process::exit(1);
+    }
 }
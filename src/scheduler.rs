@@ -0,0 +1,147 @@
+//! Runs the chips of a machine against the SNES master clock. Every chip
+//! keeps a timestamp in master clock cycles and runs ahead on its own,
+//! without being interleaved with the others cycle by cycle; only when it
+//! touches state another chip shares is that chip brought up to the same
+//! moment, so that it sees and leaves everything as it would have in
+//! lockstep. This is lazy catch-up synchronization.
+//!
+//! The CPU leads: the `Scheduler` runs it, and the other chips, the video,
+//! audio and coprocessors, sit behind its bus, which implements `Machine`
+//! and hands them out as `Component`s. The bus learns the CPU's timestamp before every access through
+//! `Bus::synchronize` and catches up the chip an access is for, and tells
+//! the CPU how long each access takes with `Bus::access_time`. Chips never
+//! run past the CPU, so the CPU never needs catching up itself; they report
+//! when they next need it with `Machine::next_event`, such as to raise an
+//! interrupt, and the scheduler brings them all up to date then and at the
+//! end of every run.
+//!
+//! ```text
+//! let mut snes = Scheduler::new(cpu, Region::Ntsc);
+//! snes.run_for(Region::Ntsc.clocks(Duration::from_millis(16)))?;
+//! ```
+
+use core::time::Duration;
+use cpu::{Bus, Cpu, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal
+}
+
+impl Region {
+    /// The master clock frequency in Hz.
+    pub fn master_clock(self) -> u64 {
+        match self {
+            Region::Ntsc => 21_477_272,
+            Region::Pal => 21_281_370
+        }
+    }
+
+    /// The number of master clock cycles in `duration`, rounded down.
+    pub fn clocks(self, duration: Duration) -> u64 {
+        (duration.as_nanos() * self.master_clock() as u128 / 1_000_000_000) as u64
+    }
+}
+
+/// A chip that runs on the master clock behind the CPU's bus.
+pub trait Component {
+    /// The master clock cycle the chip has run up to.
+    fn timestamp(&self) -> u64;
+
+    /// Runs the chip until its timestamp is at least `timestamp`. It may
+    /// overshoot by whatever it cannot split, but does nothing if it is
+    /// already there.
+    fn run_to(&mut self, timestamp: u64);
+}
+
+/// The bus of a whole machine, with the chips besides the CPU behind it.
+pub trait Machine: Bus {
+    /// Calls `f` with every chip behind the bus.
+    fn for_each_component(&mut self, f: &mut dyn FnMut(&mut dyn Component));
+
+    /// Brings every chip up to `timestamp`, by default by running each one
+    /// returned by `for_each_component` to it.
+    fn catch_up(&mut self, timestamp: u64) {
+        self.for_each_component(&mut |component| component.run_to(timestamp));
+    }
+
+    /// The earliest master clock cycle at which a chip may need the CPU to
+    /// notice it, for example by changing an interrupt line. After a catch
+    /// up to that moment it has to move on, or the scheduler catches the
+    /// chips up after every cycle.
+    fn next_event(&self) -> u64 {
+        u64::MAX
+    }
+
+    /// Whether any chip drives the IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Whether any chip drives the NMI line.
+    fn nmi(&self) -> bool {
+        false
+    }
+}
+
+pub struct Scheduler<B: Machine> {
+    cpu: Cpu<B>,
+    region: Region
+}
+
+impl<B: Machine> Scheduler<B> {
+    pub fn new(cpu: Cpu<B>, region: Region) -> Self {
+        Scheduler { cpu, region }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn cpu(&self) -> &Cpu<B> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu<B> {
+        &mut self.cpu
+    }
+
+    pub fn into_cpu(self) -> Cpu<B> {
+        self.cpu
+    }
+
+    /// The master clock timestamp of the machine, which is the CPU's.
+    pub fn timestamp(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// Runs the machine for at least `clocks` master clock cycles; the
+    /// last CPU cycle may end past them. All chips are caught up to the CPU
+    /// afterwards, also when the CPU fails.
+    pub fn run_for(&mut self, clocks: u64) -> Result<(), Error> {
+        let target = self.cpu.cycles().saturating_add(clocks);
+        while self.cpu.cycles() < target {
+            if let Err(error) = self.cpu.tick() {
+                self.catch_up();
+                return Err(error);
+            }
+            if self.cpu.cycles() >= self.cpu.bus().next_event() {
+                self.catch_up();
+            }
+        }
+        self.catch_up();
+        Ok(())
+    }
+
+    /// Brings every chip up to the CPU and passes on the interrupt lines
+    /// they drive.
+    fn catch_up(&mut self) {
+        let timestamp = self.cpu.cycles();
+        let bus = self.cpu.bus_mut();
+        bus.catch_up(timestamp);
+        let (irq, nmi) = (bus.irq(), bus.nmi());
+        self.cpu.set_irq(irq);
+        self.cpu.set_nmi(nmi);
+    }
+}